@@ -0,0 +1,462 @@
+//! Normal form of terms, hashed to pin the contents of imports
+//!
+//! Terms meaning the same value get the same digest when the normalization can tell so:
+//!
+//! - `let` bindings of closed values are inlined, and dropped once nothing refers to them,
+//! - lambdas applied to closed records are applied, as are the names after a closed record `.`,
+//! - appends and type intersections are flattened, empty records and `{}` are dropped from them,
+//! - adjacent texts of interpolations are joined.
+//!
+//! A term is closed when it refers to nothing around it: no names, `@@` or `@` outside of it.
+//! Names inside of a lambda are not replaced when the argument could have them,
+//! so a term is rewritten only when it keeps its value.
+//!
+//! The digest is the SHA-256 hash of the [CBOR encoding](crate::cbor) of the normal form.
+//! The hash pinning an import is dropped, resolved imports keep only the digests of the
+//! imported terms, so the digest of a term covers all the files it imports.
+use std::sync::Arc;
+
+use sha2::{Digest as _, Sha256};
+
+use crate::{cbor, Digest, GenType, Key, PrimType, Primitive, Term};
+
+/// SHA-256 hash of the CBOR encoding of the normal form of the term
+pub fn digest(term: &Term, resolve: impl Fn(&Term) -> Option<Digest>) -> Digest {
+    Digest(Sha256::digest(cbor::encode(&*normalize(term, resolve))).into())
+}
+
+/// Normal form of the term, `resolve` gives the digests of the imported terms
+pub fn normalize(term: &Term, resolve: impl Fn(&Term) -> Option<Digest>) -> Arc<Term> {
+    Normalizer { resolve: &resolve }.term(term, None)
+}
+
+/// What the names of a term refer to, from the innermost frame outwards
+struct Scope<'a> {
+    frame: Frame,
+    parent: Option<&'a Scope<'a>>,
+}
+
+enum Frame {
+    /// `let` binding, with the value replacing the name when the value is closed
+    Let(Key, Option<Arc<Term>>),
+    /// Argument of a lambda, it has at least the fields of the domain
+    Argument(Vec<Key>),
+    /// Closed record a lambda is applied to
+    Applied(Arc<Term>),
+    /// Record the `.` continues with, when it is a closed record
+    Chained(Option<Arc<Term>>),
+    /// Everything around the term being checked for references
+    Outside,
+}
+
+fn push<'a>(parent: Option<&'a Scope<'a>>, frame: Frame) -> Scope<'a> {
+    Scope { frame, parent }
+}
+
+fn frames<'a>(scope: Option<&'a Scope<'a>>) -> impl Iterator<Item = &'a Scope<'a>> {
+    std::iter::successors(scope, |scope| scope.parent)
+}
+
+/// Frame of the value of `@@`, the `let` bindings are skipped
+fn current<'a>(scope: Option<&'a Scope<'a>>) -> Option<&'a Scope<'a>> {
+    frames(scope).find(|scope| !matches!(scope.frame, Frame::Let(..)))
+}
+
+/// Scope the functions applied with `@` are looked up in
+fn outer<'a>(scope: Option<&'a Scope<'a>>) -> Option<&'a Scope<'a>> {
+    current(scope).and_then(|scope| scope.parent)
+}
+
+struct Normalizer<'r> {
+    resolve: &'r dyn Fn(&Term) -> Option<Digest>,
+}
+
+impl Normalizer<'_> {
+    fn term(&self, term: &Term, scope: Option<&Scope>) -> Arc<Term> {
+        let normal = match term {
+            Term::Prim(_) | Term::Empty | Term::Type(GenType::Prim(_)) => term.clone(),
+            Term::Type(GenType::Field { name, typ }) => Term::Type(GenType::Field {
+                name: name.clone(),
+                typ: self.term(typ, scope),
+            }),
+            Term::Type(GenType::Function { dom, codom }) => Term::Type(GenType::Function {
+                dom: self.term(dom, scope),
+                codom: self.term(codom, scope),
+            }),
+            Term::Type(GenType::And { left, right }) => {
+                let mut types = vec![];
+                for side in [left, right] {
+                    intersected(self.term(side, scope), &mut types);
+                }
+                let and = |left, right| Arc::new(Term::Type(GenType::And { left, right }));
+                return types
+                    .into_iter()
+                    .reduce(and)
+                    .unwrap_or_else(|| Arc::new(Term::Type(GenType::Prim(PrimType::Any))));
+            }
+            Term::Append { left, right } => {
+                let mut records = vec![];
+                for side in [left, right] {
+                    appended(self.term(side, scope), &mut records);
+                }
+                let append = |left, right| Arc::new(Term::Append { left, right });
+                return records
+                    .into_iter()
+                    .reduce(append)
+                    .unwrap_or_else(|| Arc::new(Term::Empty));
+            }
+            Term::Set { name, value } => Term::Set {
+                name: name.clone(),
+                value: self.term(value, scope),
+            },
+            Term::Get(key) => return lookup(key, scope).unwrap_or_else(|| Arc::new(term.clone())),
+            Term::Lambda { dom, body } => {
+                let dom = self.term(dom, scope);
+                let argument = push(scope, Frame::Argument(domain_fields(&dom)));
+                let body = self.term(body, Some(&argument));
+                Term::Lambda { dom, body }
+            }
+            Term::Unlambda(func) => Term::Unlambda(self.term(func, outer(scope))),
+            Term::Then { first, next } => {
+                let first = self.term(first, scope);
+                let record = is_record(&first) && is_closed(&first);
+                let chained = push(scope, Frame::Chained(record.then(|| first.clone())));
+                let next = self.term(next, Some(&chained));
+                return match &*next {
+                    Term::Unlambda(func) if record => self.apply(first, func.clone(), scope),
+                    _ if record && is_closed(&next) => next,
+                    _ => Arc::new(Term::Then { first, next }),
+                };
+            }
+            Term::Let { name, value, body } => {
+                let value = self.term(value, scope);
+                let inlined = is_closed(&value).then(|| value.clone());
+                let inline = inlined.is_some();
+                let binding = push(scope, Frame::Let(name.clone(), inlined));
+                let body = self.term(body, Some(&binding));
+                if inline && !references(&body).names.contains(name) {
+                    return body;
+                }
+                Term::Let {
+                    name: name.clone(),
+                    value,
+                    body,
+                }
+            }
+            Term::Reflect => match current(scope).map(|scope| &scope.frame) {
+                Some(Frame::Applied(record) | Frame::Chained(Some(record))) => {
+                    return record.clone()
+                }
+                _ => Term::Reflect,
+            },
+            Term::Interpolate(parts) => {
+                let mut joined: Vec<Arc<Term>> = vec![];
+                for part in parts {
+                    let part = self.term(part, scope);
+                    match (joined.last().map(|last| &**last), &*part) {
+                        (
+                            Some(Term::Prim(Primitive::Text(left))),
+                            Term::Prim(Primitive::Text(right)),
+                        ) => {
+                            let text = Primitive::Text(format!("{left}{right}"));
+                            *joined.last_mut().unwrap() = Arc::new(Term::Prim(text));
+                        }
+                        _ => joined.push(part),
+                    }
+                }
+                match &joined[..] {
+                    [text] if matches!(**text, Term::Prim(Primitive::Text(_))) => {
+                        return text.clone()
+                    }
+                    _ => Term::Interpolate(joined),
+                }
+            }
+            Term::Import { path, .. } => match (self.resolve)(term) {
+                Some(digest) => Term::Import {
+                    path: String::new(),
+                    hash: Some(Box::new(digest)),
+                },
+                None => Term::Import {
+                    path: path.clone(),
+                    hash: None,
+                },
+            },
+        };
+        Arc::new(normal)
+    }
+
+    /// Applies the function to the record after `.`, when both are known
+    fn apply(&self, record: Arc<Term>, func: Arc<Term>, scope: Option<&Scope>) -> Arc<Term> {
+        if let Term::Lambda { body, .. } = &*func {
+            let applied = push(scope, Frame::Applied(record.clone()));
+            let body = self.term(body, Some(&applied));
+            let references = references(&body);
+            let fields = record_fields(&record);
+            // names an inner argument may lack still refer to the record
+            if !references.context && !references.names.iter().any(|name| fields.contains(name)) {
+                return body;
+            }
+        }
+        let next = Arc::new(Term::Unlambda(func));
+        Arc::new(Term::Then {
+            first: record,
+            next,
+        })
+    }
+}
+
+/// Value replacing the name, when it is known
+fn lookup(key: &Key, scope: Option<&Scope>) -> Option<Arc<Term>> {
+    for scope in frames(scope) {
+        match &scope.frame {
+            Frame::Let(name, value) if name == key => return value.clone(),
+            Frame::Let(..) => {}
+            Frame::Applied(record) => {
+                if let Some(value) = field(record, key) {
+                    return Some(value);
+                }
+            }
+            Frame::Chained(Some(record)) => return field(record, key),
+            Frame::Argument(_) | Frame::Chained(None) | Frame::Outside => return None,
+        }
+    }
+    None
+}
+
+/// What a term refers to outside of it
+#[derive(Default)]
+struct References {
+    names: Vec<Key>,
+    /// `@@` or `@` refer to the context around the term
+    context: bool,
+}
+
+fn references(term: &Term) -> References {
+    let mut found = References::default();
+    find_references(term, Some(&push(None, Frame::Outside)), &mut found);
+    found
+}
+
+fn is_closed(term: &Term) -> bool {
+    let references = references(term);
+    references.names.is_empty() && !references.context
+}
+
+fn find_references(term: &Term, scope: Option<&Scope>, found: &mut References) {
+    let is_outside = |scope: Option<&Scope>| {
+        matches!(
+            scope,
+            Some(Scope {
+                frame: Frame::Outside,
+                ..
+            })
+        )
+    };
+    match term {
+        Term::Get(key) => {
+            for scope in frames(scope) {
+                match &scope.frame {
+                    Frame::Let(name, _) if name == key => return,
+                    Frame::Argument(fields) if fields.contains(key) => return,
+                    Frame::Applied(record) if field(record, key).is_some() => return,
+                    Frame::Chained(_) => return,
+                    Frame::Outside => {
+                        if !found.names.contains(key) {
+                            found.names.push(key.clone());
+                        }
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Term::Reflect => found.context |= is_outside(current(scope)),
+        Term::Unlambda(func) => match is_outside(current(scope)) {
+            true => found.context = true,
+            false => find_references(func, outer(scope), found),
+        },
+        Term::Lambda { dom, body } => {
+            find_references(dom, scope, found);
+            let argument = push(scope, Frame::Argument(domain_fields(dom)));
+            find_references(body, Some(&argument), found);
+        }
+        Term::Then { first, next } => {
+            find_references(first, scope, found);
+            find_references(next, Some(&push(scope, Frame::Chained(None))), found);
+        }
+        Term::Let { name, value, body } => {
+            find_references(value, scope, found);
+            let binding = push(scope, Frame::Let(name.clone(), None));
+            find_references(body, Some(&binding), found);
+        }
+        Term::Prim(_) | Term::Empty | Term::Import { .. } => {}
+        Term::Type(GenType::Prim(_)) => {}
+        Term::Type(GenType::Field { typ, .. }) => find_references(typ, scope, found),
+        Term::Type(
+            GenType::Function {
+                dom: left,
+                codom: right,
+            }
+            | GenType::And { left, right },
+        )
+        | Term::Append { left, right } => {
+            find_references(left, scope, found);
+            find_references(right, scope, found);
+        }
+        Term::Set { value, .. } => find_references(value, scope, found),
+        Term::Interpolate(parts) => {
+            for part in parts {
+                find_references(part, scope, found);
+            }
+        }
+    }
+}
+
+/// Pushes the records of an append, leaving out the empty ones
+fn appended(term: Arc<Term>, records: &mut Vec<Arc<Term>>) {
+    match &*term {
+        Term::Append { left, right } => {
+            appended(left.clone(), records);
+            appended(right.clone(), records);
+        }
+        Term::Empty => {}
+        _ => records.push(term),
+    }
+}
+
+/// Pushes the types of an intersection, leaving out `{}`
+fn intersected(term: Arc<Term>, types: &mut Vec<Arc<Term>>) {
+    match &*term {
+        Term::Type(GenType::And { left, right }) => {
+            intersected(left.clone(), types);
+            intersected(right.clone(), types);
+        }
+        Term::Type(GenType::Prim(PrimType::Any)) => {}
+        _ => types.push(term),
+    }
+}
+
+fn is_record(term: &Term) -> bool {
+    match term {
+        Term::Empty | Term::Set { .. } => true,
+        Term::Append { left, right } => is_record(left) && is_record(right),
+        _ => false,
+    }
+}
+
+/// Value of the field of a record, the last one set wins
+fn field(record: &Term, key: &Key) -> Option<Arc<Term>> {
+    match record {
+        Term::Set { name, value } if name == key => Some(value.clone()),
+        Term::Append { left, right } => field(right, key).or_else(|| field(left, key)),
+        _ => None,
+    }
+}
+
+fn record_fields(record: &Term) -> Vec<Key> {
+    match record {
+        Term::Set { name, .. } => vec![name.clone()],
+        Term::Append { left, right } => [record_fields(left), record_fields(right)].concat(),
+        _ => vec![],
+    }
+}
+
+/// Fields every argument of a lambda with the domain has
+fn domain_fields(dom: &Term) -> Vec<Key> {
+    match dom {
+        Term::Type(GenType::Field { name, .. }) => vec![name.clone()],
+        Term::Type(GenType::And { left, right }) => {
+            [domain_fields(left), domain_fields(right)].concat()
+        }
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+use crate::parse::parse_term;
+
+#[cfg(test)]
+fn normal(input: &str) -> Arc<Term> {
+    normalize(&parse_term(input).unwrap(), |_| None)
+}
+
+#[test]
+fn normalize_examples() {
+    let same = [
+        ("let p = 80 in (port = p)", "(port = 80)"),
+        ("let a = 1, b = a in (x = b, y = a)", "(x = 1, y = 1)"),
+        ("(\\{x: #int} => (port = x)) (x = 80)", "(port = 80)"),
+        (
+            "let f = \\{x: #int} => (port = x) in f (x = 80)",
+            "(port = 80)",
+        ),
+        ("(a = 1, b = 2).b", "2"),
+        ("(a = 1).@@", "(a = 1)"),
+        ("(\\{} => @@) (x = 1)", "(x = 1)"),
+        ("{} & {a: #int} & ({b: #text} & {})", "{a: #int, b: #text}"),
+        ("let n = 'x' in \"${n}${'y'}\"", "'xy'"),
+        ("let T = {x: #int} in \\T => x", "\\{x: #int} => x"),
+        ("let p = 1 in \\{p: #int} => p", "\\{p: #int} => p"),
+    ];
+    for (input, expected) in same {
+        assert_eq!(normal(input), normal(expected), "{input}");
+    }
+    let set = |name: &str| parse_term(&format!("({name} = 1)")).unwrap();
+    let append = |left, right| Arc::new(Term::Append { left, right });
+    let empty = Arc::new(Term::Empty);
+    let nested = append(append(empty.clone(), set("a")), append(empty, set("b")));
+    assert_eq!(normalize(&nested, |_| None), normal("(a = 1, b = 1)"));
+}
+
+#[test]
+fn normalize_keeps_values() {
+    // the argument could have the field `p`, so the name inside is not replaced
+    let kept = [
+        ("let p = 80 in \\{x: #int} => p", "\\{x: #int} => 80"),
+        ("let p = x in (port = p)", "(port = x)"),
+        (
+            "(\\{x: #int} => \\{y: #int} => x) (x = 1)",
+            "\\{y: #int} => 1",
+        ),
+        ("(a = 1).b", "b"),
+    ];
+    for (input, other) in kept {
+        assert_ne!(normal(input), normal(other), "{input}");
+        assert_eq!(
+            normal(input),
+            normalize(&normal(input), |_| None),
+            "{input}"
+        );
+    }
+}
+
+#[test]
+fn digest_examples() {
+    let digest_of = |input: &str| digest(&parse_term(input).unwrap(), |_| None);
+    assert_eq!(
+        digest_of("(port = 80)"),
+        digest_of("let p = 80 in (port = p)")
+    );
+    let compact = digest_of("let a = 1 in (b = a, c = \"${a}!\")");
+    let spread =
+        digest_of("let\n  a = 1, // one\nin (\n  /* fields */ b = a,\n  'c' = \"${a}!\",\n)");
+    assert_eq!(compact, spread);
+    assert_ne!(compact, digest_of("let a = 1 in (b = a, c = \"${a}?\")"));
+    assert_ne!(digest_of("(a = 1)"), digest_of("(a = 1.0)"));
+}
+
+#[test]
+fn digest_imports() {
+    let pinned = format!("import 'a.kers' sha256:{}", "12".repeat(32));
+    let pinned = parse_term(&pinned).unwrap();
+    let plain = parse_term("import 'a.kers'").unwrap();
+    let other = parse_term("import '../lib/a.kers'").unwrap();
+    assert_eq!(digest(&pinned, |_| None), digest(&plain, |_| None));
+    assert_ne!(digest(&plain, |_| None), digest(&other, |_| None));
+    let one = Digest([1; 32]);
+    assert_eq!(digest(&plain, |_| Some(one)), digest(&other, |_| Some(one)));
+    assert_ne!(
+        digest(&plain, |_| Some(one)),
+        digest(&plain, |_| Some(Digest([2; 32])))
+    );
+}
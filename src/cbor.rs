@@ -0,0 +1,455 @@
+//! Binary encoding of terms in CBOR, for storing parsed terms and loading them without the parser
+//!
+//! The encoded item is wrapped into the array `[version, item]`, the current version is [`VERSION`].
+//! Items are written with the deterministic encoding of RFC 8949, section 4.2: the shortest forms
+//! of integers, lengths and floats preserving the bits, and no maps, so every term has exactly one encoding.
+//!
+//! Keys are texts for names and unsigned integers for indices.
+//! Terms are arrays starting with an unsigned tag, followed by the fields:
+//!
+//! | tag  | term                                            |
+//! |------|-------------------------------------------------|
+//! | `0`  | `[0]` empty record                              |
+//! | `1`  | `[1, integer]`                                  |
+//! | `2`  | `[2, float]`                                    |
+//! | `3`  | `[3, text]`                                     |
+//! | `4`  | `[4, n]` primitive type, `0` text, `1` int, `2` float, `3` universe, `4` any |
+//! | `5`  | `[5, key, type]` field type                     |
+//! | `6`  | `[6, domain, codomain]` function type           |
+//! | `7`  | `[7, left, right]` type intersection            |
+//! | `8`  | `[8, first, second, ...]` appends, nested on the left |
+//! | `9`  | `[9, key, value]` set                           |
+//! | `10` | `[10, key]` get                                 |
+//! | `11` | `[11, domain, body]` lambda                     |
+//! | `12` | `[12, body]` unlambda                           |
+//! | `13` | `[13, first, next, ...]` thens, nested on the left |
+//! | `14` | `[14, key, value, ..., body]` lets, nested on the right |
+//! | `15` | `[15]` reflect                                  |
+//! | `16` | `[16, part, ...]` interpolation                 |
+//! | `17` | `[17, path]` or `[17, path, hash]` import, the hash is 32 bytes |
+//!
+//! Records are chains of appends, writing them as a single array keeps the nesting
+//! of the encoding independent of the number of fields, the same goes for the chains
+//! of `.` and the bindings of `let`.
+//! Decoding stops at [`NESTING_LIMIT`] nested arrays, before deep items exhaust the stack.
+//! Primitives and types are written as the corresponding terms.
+use std::sync::Arc;
+
+use ciborium::value::Value;
+use thiserror::Error;
+
+use crate::{Digest, Float, GenType, Key, PrimType, Primitive, Term, Type};
+
+/// Version of the encoding written by [`encode`], changed whenever the layout of the items changes
+pub const VERSION: u64 = 2;
+
+/// Nesting of the arrays [`decode`] accepts, deeper items are rejected
+pub const NESTING_LIMIT: usize = 512;
+
+#[derive(Error, Debug)]
+pub enum CborError {
+    #[error("invalid CBOR: {0}")]
+    Cbor(#[from] ciborium::de::Error<std::io::Error>),
+    #[error("unsupported encoding version {0}, expected {VERSION}")]
+    Version(u64),
+    #[error("malformed {0}")]
+    Malformed(&'static str),
+    #[error("unexpected data after the encoded item")]
+    Trailing,
+}
+
+/// Item with a CBOR encoding
+pub trait Cbor: Sized {
+    fn to_cbor(&self) -> Value;
+    fn from_cbor(value: &Value) -> Result<Self, CborError>;
+}
+
+/// Versioned encoding of the item
+pub fn encode<T: Cbor>(item: &T) -> Vec<u8> {
+    let envelope = Value::Array(vec![VERSION.into(), item.to_cbor()]);
+    let mut out = vec![];
+    ciborium::into_writer(&envelope, &mut out).expect("writing to a vector");
+    out
+}
+
+/// Item encoded by [`encode`]
+pub fn decode<T: Cbor>(mut bytes: &[u8]) -> Result<T, CborError> {
+    let envelope: Value =
+        ciborium::de::from_reader_with_recursion_limit(&mut bytes, NESTING_LIMIT)?;
+    if !bytes.is_empty() {
+        return Err(CborError::Trailing);
+    }
+    match envelope {
+        Value::Array(items) => match items.as_slice() {
+            [Value::Integer(version), item] => match u64::try_from(*version) {
+                Ok(VERSION) => T::from_cbor(item),
+                Ok(version) => Err(CborError::Version(version)),
+                Err(_) => Err(CborError::Malformed("version")),
+            },
+            _ => Err(CborError::Malformed("envelope")),
+        },
+        _ => Err(CborError::Malformed("envelope")),
+    }
+}
+
+impl Cbor for Key {
+    fn to_cbor(&self) -> Value {
+        match self {
+            Key::Name(name) => Value::Text(name.clone()),
+            Key::Index(idx) => Value::Integer((*idx).into()),
+        }
+    }
+
+    fn from_cbor(value: &Value) -> Result<Self, CborError> {
+        match value {
+            Value::Text(name) => Ok(Key::Name(name.clone())),
+            Value::Integer(idx) => usize::try_from(*idx)
+                .map(Key::Index)
+                .map_err(|_| CborError::Malformed("key")),
+            _ => Err(CborError::Malformed("key")),
+        }
+    }
+}
+
+impl Cbor for Primitive {
+    fn to_cbor(&self) -> Value {
+        match self {
+            Primitive::Long(i) => node(1, [(*i).into()]),
+            Primitive::Float(f) => node(2, [Value::Float(f.0)]),
+            Primitive::Text(s) => node(3, [Value::Text(s.clone())]),
+        }
+    }
+
+    fn from_cbor(value: &Value) -> Result<Self, CborError> {
+        match Term::from_cbor(value)? {
+            Term::Prim(prim) => Ok(prim),
+            _ => Err(CborError::Malformed("primitive")),
+        }
+    }
+}
+
+impl Cbor for Type {
+    fn to_cbor(&self) -> Value {
+        match self {
+            GenType::Prim(prim) => {
+                let code = match prim {
+                    PrimType::Text => 0,
+                    PrimType::Long => 1,
+                    PrimType::Float => 2,
+                    PrimType::Universe => 3,
+                    PrimType::Any => 4,
+                };
+                node(4, [code.into()])
+            }
+            GenType::Field { name, typ } => node(5, [name.to_cbor(), typ.to_cbor()]),
+            GenType::Function { dom, codom } => node(6, [dom.to_cbor(), codom.to_cbor()]),
+            GenType::And { left, right } => node(7, [left.to_cbor(), right.to_cbor()]),
+        }
+    }
+
+    fn from_cbor(value: &Value) -> Result<Self, CborError> {
+        match Term::from_cbor(value)? {
+            Term::Type(typ) => Ok(typ),
+            _ => Err(CborError::Malformed("type")),
+        }
+    }
+}
+
+impl Cbor for Term {
+    fn to_cbor(&self) -> Value {
+        match self {
+            Term::Type(typ) => typ.to_cbor(),
+            Term::Prim(prim) => prim.to_cbor(),
+            Term::Empty => node(0, []),
+            Term::Append { .. } => {
+                let mut operands = vec![];
+                let mut term = self;
+                while let Term::Append { left, right } = term {
+                    operands.push(right.to_cbor());
+                    term = left;
+                }
+                operands.push(term.to_cbor());
+                operands.push(8.into());
+                operands.reverse();
+                Value::Array(operands)
+            }
+            Term::Set { name, value } => node(9, [name.to_cbor(), value.to_cbor()]),
+            Term::Get(key) => node(10, [key.to_cbor()]),
+            Term::Lambda { dom, body } => node(11, [dom.to_cbor(), body.to_cbor()]),
+            Term::Unlambda(body) => node(12, [body.to_cbor()]),
+            Term::Then { .. } => {
+                let mut operands = vec![];
+                let mut term = self;
+                while let Term::Then { first, next } = term {
+                    operands.push(next.to_cbor());
+                    term = first;
+                }
+                operands.push(term.to_cbor());
+                operands.push(13.into());
+                operands.reverse();
+                Value::Array(operands)
+            }
+            Term::Let { .. } => {
+                let mut operands = vec![14.into()];
+                let mut term = self;
+                while let Term::Let { name, value, body } = term {
+                    operands.extend([name.to_cbor(), value.to_cbor()]);
+                    term = body;
+                }
+                operands.push(term.to_cbor());
+                Value::Array(operands)
+            }
+            Term::Reflect => node(15, []),
+            Term::Interpolate(parts) => node(16, parts.iter().map(|part| part.to_cbor())),
+            Term::Import { path, hash } => {
+                let path = Value::Text(path.clone());
+                match hash {
+                    Some(hash) => node(17, [path, Value::Bytes(hash.0.to_vec())]),
+                    None => node(17, [path]),
+                }
+            }
+        }
+    }
+
+    fn from_cbor(value: &Value) -> Result<Self, CborError> {
+        let malformed = || CborError::Malformed("term");
+        let Value::Array(items) = value else {
+            return Err(malformed());
+        };
+        let Some((Value::Integer(tag), fields)) = items.split_first() else {
+            return Err(malformed());
+        };
+        let term = |value: &Value| Term::from_cbor(value).map(Arc::new);
+        let typ = |typ: GenType<Term>| Ok(Term::Type(typ));
+        match (u64::try_from(*tag).map_err(|_| malformed())?, fields) {
+            (0, []) => Ok(Term::Empty),
+            (1, [Value::Integer(i)]) => {
+                let i = i64::try_from(*i).map_err(|_| CborError::Malformed("integer"))?;
+                Ok(Term::Prim(Primitive::Long(i)))
+            }
+            (2, [Value::Float(f)]) => Ok(Term::Prim(Primitive::Float(Float(*f)))),
+            (3, [Value::Text(s)]) => Ok(Term::Prim(Primitive::Text(s.clone()))),
+            (4, [Value::Integer(code)]) => {
+                let prim = match u8::try_from(*code) {
+                    Ok(0) => PrimType::Text,
+                    Ok(1) => PrimType::Long,
+                    Ok(2) => PrimType::Float,
+                    Ok(3) => PrimType::Universe,
+                    Ok(4) => PrimType::Any,
+                    _ => return Err(CborError::Malformed("primitive type")),
+                };
+                typ(GenType::Prim(prim))
+            }
+            (5, [name, field]) => typ(GenType::Field {
+                name: Key::from_cbor(name)?,
+                typ: term(field)?,
+            }),
+            (6, [dom, codom]) => typ(GenType::Function {
+                dom: term(dom)?,
+                codom: term(codom)?,
+            }),
+            (7, [left, right]) => typ(GenType::And {
+                left: term(left)?,
+                right: term(right)?,
+            }),
+            (8, [first, rest @ ..]) if !rest.is_empty() => {
+                rest.iter()
+                    .try_fold(Term::from_cbor(first)?, |left, right| {
+                        Ok(Term::Append {
+                            left: Arc::new(left),
+                            right: term(right)?,
+                        })
+                    })
+            }
+            (9, [name, value]) => Ok(Term::Set {
+                name: Key::from_cbor(name)?,
+                value: term(value)?,
+            }),
+            (10, [key]) => Ok(Term::Get(Key::from_cbor(key)?)),
+            (11, [dom, body]) => Ok(Term::Lambda {
+                dom: term(dom)?,
+                body: term(body)?,
+            }),
+            (12, [body]) => Ok(Term::Unlambda(term(body)?)),
+            (13, [first, rest @ ..]) if !rest.is_empty() => {
+                rest.iter()
+                    .try_fold(Term::from_cbor(first)?, |first, next| {
+                        Ok(Term::Then {
+                            first: Arc::new(first),
+                            next: term(next)?,
+                        })
+                    })
+            }
+            (14, [bindings @ .., body]) if !bindings.is_empty() && bindings.len() % 2 == 0 => {
+                bindings
+                    .chunks(2)
+                    .try_rfold(Term::from_cbor(body)?, |body, binding| {
+                        Ok(Term::Let {
+                            name: Key::from_cbor(&binding[0])?,
+                            value: term(&binding[1])?,
+                            body: Arc::new(body),
+                        })
+                    })
+            }
+            (15, []) => Ok(Term::Reflect),
+            (16, parts) => Ok(Term::Interpolate(
+                parts.iter().map(term).collect::<Result<_, _>>()?,
+            )),
+            (17, [Value::Text(path)]) => Ok(Term::Import {
+                path: path.clone(),
+                hash: None,
+            }),
+            (17, [Value::Text(path), Value::Bytes(hash)]) => {
+                let hash = hash
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| CborError::Malformed("hash"))?;
+                Ok(Term::Import {
+                    path: path.clone(),
+                    hash: Some(Box::new(Digest(hash))),
+                })
+            }
+            _ => Err(malformed()),
+        }
+    }
+}
+
+/// Array of the tag followed by the fields
+fn node(tag: u64, fields: impl IntoIterator<Item = Value>) -> Value {
+    Value::Array([tag.into()].into_iter().chain(fields).collect())
+}
+
+#[cfg(test)]
+use {
+    crate::{arb_term, parse::parse_term, ToTerm},
+    proptest::prelude::*,
+};
+
+#[test]
+fn encode_examples() {
+    assert_eq!(
+        encode(&Key::Name("a".to_string())),
+        [0x82, 0x02, 0x61, b'a']
+    );
+    assert_eq!(encode(&Key::Index(24)), [0x82, 0x02, 0x18, 24]);
+    let term = parse_term("(a = 1, 'b' = 1.5)").unwrap();
+    let expected = [
+        [0x82, 0x02, 0x83, 0x08].as_slice(),
+        &[0x83, 0x09, 0x61, b'a', 0x82, 0x01, 0x01],
+        &[0x83, 0x09, 0x61, b'b', 0x82, 0x02, 0xf9, 0x3e, 0x00],
+    ]
+    .concat();
+    assert_eq!(encode(term.as_ref()), expected);
+    let typ = Type::Function {
+        dom: Term::Type(Type::Prim(PrimType::Any)).to_arc_term(),
+        codom: Term::Type(Type::Prim(PrimType::Float)).to_arc_term(),
+    };
+    assert_eq!(
+        encode(&typ),
+        [0x82, 0x02, 0x83, 0x06, 0x82, 0x04, 0x04, 0x82, 0x04, 0x02]
+    );
+    assert_eq!(decode::<Type>(&encode(&typ)).unwrap(), typ);
+    let prim = Primitive::Text("é".to_string());
+    assert_eq!(decode::<Primitive>(&encode(&prim)).unwrap(), prim);
+}
+
+#[test]
+fn decode_wide_records() {
+    let term = (0..2000).fold(Term::Empty, |left, idx| Term::Append {
+        left: left.to_arc_term(),
+        right: Term::Set {
+            name: Key::Index(idx),
+            value: (idx as i64).to_arc_term(),
+        }
+        .to_arc_term(),
+    });
+    let decoded: Term = decode(&encode(&term)).unwrap();
+    assert_eq!(decoded, term);
+}
+
+#[test]
+fn decode_deep_terms() {
+    let lets = (0..300).rfold(Term::get("a0"), |body, idx| Term::Let {
+        name: Key::Name(format!("a{idx}")),
+        value: (idx as i64).to_arc_term(),
+        body: body.to_arc_term(),
+    });
+    let thens = (0..300).fold(Term::Empty, |first, _| Term::Then {
+        first: first.to_arc_term(),
+        next: Term::Reflect.to_arc_term(),
+    });
+    let records = (0..300).fold(Term::Empty, |value, idx| Term::Set {
+        name: Key::Index(idx),
+        value: value.to_arc_term(),
+    });
+    for term in [lets, thens, records] {
+        let decoded: Term = decode(&encode(&term)).unwrap();
+        assert_eq!(decoded, term);
+    }
+    let mut deeper = vec![0x82, VERSION as u8];
+    for _ in 0..NESTING_LIMIT {
+        deeper.extend([0x83, 0x09, 0x00]);
+    }
+    deeper.extend([0x81, 0x00]);
+    assert!(matches!(
+        decode::<Term>(&deeper),
+        Err(CborError::Cbor(ciborium::de::Error::RecursionLimitExceeded))
+    ));
+}
+
+#[test]
+fn decode_errors() {
+    let encoded = encode(&Term::Reflect);
+    let mut next = encoded.clone();
+    next[1] = 0x03;
+    assert_eq!(
+        decode::<Term>(&next).unwrap_err().to_string(),
+        "unsupported encoding version 3, expected 2"
+    );
+    let mut trailing = encoded.clone();
+    trailing.push(0x00);
+    assert!(matches!(
+        decode::<Term>(&trailing),
+        Err(CborError::Trailing)
+    ));
+    assert!(matches!(
+        decode::<Term>(&encoded[..2]),
+        Err(CborError::Cbor(_))
+    ));
+    let err = decode::<Type>(&encoded).unwrap_err();
+    assert_eq!(err.to_string(), "malformed type");
+    let set = node(9, [Value::Bool(true), node(0, [])]);
+    let mut bytes = vec![];
+    ciborium::into_writer(&Value::Array(vec![VERSION.into(), set]), &mut bytes).unwrap();
+    assert_eq!(
+        decode::<Term>(&bytes).unwrap_err().to_string(),
+        "malformed key"
+    );
+    let append = node(8, [node(0, [])]);
+    let mut bytes = vec![];
+    ciborium::into_writer(&Value::Array(vec![VERSION.into(), append]), &mut bytes).unwrap();
+    assert_eq!(
+        decode::<Term>(&bytes).unwrap_err().to_string(),
+        "malformed term"
+    );
+    let unbound = node(14, [Value::Text("a".to_string()), node(0, [])]);
+    let mut bytes = vec![];
+    ciborium::into_writer(&Value::Array(vec![VERSION.into(), unbound]), &mut bytes).unwrap();
+    assert_eq!(
+        decode::<Term>(&bytes).unwrap_err().to_string(),
+        "malformed term"
+    );
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1000))]
+
+    #[test]
+    fn encode_decode_roundtrip(term in arb_term()) {
+        let encoded = encode(term.as_ref());
+        let decoded: Term = decode(&encoded).unwrap();
+        prop_assert_eq!(&decoded, term.as_ref());
+        prop_assert_eq!(encode(&decoded), encoded);
+    }
+}
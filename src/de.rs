@@ -0,0 +1,471 @@
+use std::fmt;
+
+use serde::{
+    de::{
+        self, value::StringDeserializer, DeserializeOwned, DeserializeSeed, IntoDeserializer,
+        MapAccess, SeqAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize,
+};
+use thiserror::Error;
+
+use crate::{
+    diagnostics::{Diagnostic, Style},
+    evaltime::{
+        checking::TypeChecking,
+        evaluate::Evaluation,
+        values::{is_positional, Value},
+    },
+    export::{key_name, Path},
+    language::source::Sources,
+    parse::{parse_str, ParseError, Parsed},
+    Key, Primitive,
+};
+
+#[derive(Error, Debug)]
+pub enum DeError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("{}", .diagnostic.render(.sources, Style::Plain).trim_end())]
+    Type {
+        diagnostic: Diagnostic,
+        sources: Sources,
+    },
+    #[error("{}", .diagnostic.render(.sources, Style::Plain).trim_end())]
+    Eval {
+        diagnostic: Diagnostic,
+        sources: Sources,
+    },
+    #[error("at {path}: {msg}")]
+    Value { path: Path, msg: String },
+}
+
+impl DeError {
+    /// Places the error raised for the value at `path`, unless it already comes from a nested value
+    fn at(self, path: &Path) -> Self {
+        match self {
+            DeError::Value { path: inner, msg } if inner.0.is_empty() => DeError::Value {
+                path: path.clone(),
+                msg,
+            },
+            other => other,
+        }
+    }
+}
+
+impl de::Error for DeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        DeError::Value {
+            path: Path::default(),
+            msg: msg.to_string(),
+        }
+    }
+}
+
+/// Parses, type-checks and evaluates the kers source, and deserializes the resulting value
+pub fn from_str<T: DeserializeOwned>(input: &str) -> Result<T, DeError> {
+    let Parsed {
+        term,
+        spans,
+        sources,
+        ..
+    } = parse_str(input)?;
+    let mut checking = TypeChecking::new(());
+    if let Err(err) = checking.check(&term, &Value::record_type(vec![])) {
+        let span = checking.failed_at().and_then(|node| spans.get_node(node));
+        let diagnostic = Diagnostic::typing(&err, span);
+        return Err(DeError::Type {
+            diagnostic,
+            sources,
+        });
+    }
+    let mut evaluation = Evaluation::new(());
+    let value = match evaluation.eval(&term, &Value::empty()) {
+        Ok(value) => value,
+        Err(err) => {
+            let span = evaluation.failed_at().and_then(|node| spans.get_node(node));
+            let diagnostic = Diagnostic::evaluation(&err, span);
+            return Err(DeError::Eval {
+                diagnostic,
+                sources,
+            });
+        }
+    };
+    from_value(&value)
+}
+
+/// Deserializes the evaluated value
+///
+/// Records are read as maps or structs, records keyed by consecutive indices as sequences or tuples,
+/// numbers and texts as scalars. The empty record `()` stands for the unit and for `None`.
+/// Enum variants are texts, or records with a single field named after the variant holding its contents.
+pub fn from_value<'de, T: Deserialize<'de>, P>(value: &'de Value<P>) -> Result<T, DeError> {
+    let path = Path::default();
+    T::deserialize(ValueDeserializer { value, path: &path }).map_err(|err| err.at(&path))
+}
+
+struct ValueDeserializer<'de, 'p, P> {
+    value: &'de Value<P>,
+    path: &'p Path,
+}
+
+impl<'de, 'p, P> ValueDeserializer<'de, 'p, P> {
+    fn unsupported(&self) -> DeError {
+        let what = match self.value {
+            Value::Lambda { .. } => "a function".to_string(),
+            Value::Type(_) => format!("the type {}", self.value),
+            Value::Variable(_) => "an unknown type".to_string(),
+            Value::External(_) => "a plugin value".to_string(),
+            Value::Prim(_) | Value::Record { .. } => format!("the value {}", self.value),
+        };
+        DeError::Value {
+            path: self.path.clone(),
+            msg: format!("{what} can not be deserialized"),
+        }
+    }
+
+    fn fields(&self) -> Option<&'de [(Key, Value<P>)]> {
+        match self.value {
+            Value::Record { fields } => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+impl<'de, 'p, P> de::Deserializer<'de> for ValueDeserializer<'de, 'p, P> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self.value {
+            Value::Prim(Primitive::Long(n)) => visitor.visit_i64(*n),
+            Value::Prim(Primitive::Float(x)) => visitor.visit_f64(x.0),
+            Value::Prim(Primitive::Text(text)) => visitor.visit_borrowed_str(text),
+            Value::Record { fields } if fields.is_empty() => visitor.visit_unit(),
+            Value::Record { fields } if is_positional(fields) => self.deserialize_seq(visitor),
+            Value::Record { .. } => self.deserialize_map(visitor),
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self.fields() {
+            Some([]) => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self.fields() {
+            Some([]) => visitor.visit_unit(),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self.fields() {
+            Some(fields) if is_positional(fields) => visitor.visit_seq(Fields {
+                fields: fields.iter(),
+                path: self.path,
+                value: None,
+            }),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self.fields() {
+            Some(fields) => visitor.visit_map(Fields {
+                fields: fields.iter(),
+                path: self.path,
+                value: None,
+            }),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        match self.value {
+            Value::Prim(Primitive::Text(variant)) => {
+                visitor.visit_enum(variant.as_str().into_deserializer())
+            }
+            Value::Record { fields } if fields.len() == 1 => {
+                let (key, value) = &fields[0];
+                visitor.visit_enum(Variant {
+                    name: key_name(key),
+                    value,
+                    path: self.path.with(key),
+                })
+            }
+            _ => Err(DeError::Value {
+                path: self.path.clone(),
+                msg: format!(
+                    "expected a text or a record with a single field for an enum, got {}",
+                    self.value
+                ),
+            }),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf identifier
+    }
+}
+
+/// Fields of a record read as a sequence or as a map
+struct Fields<'de, 'p, P> {
+    fields: std::slice::Iter<'de, (Key, Value<P>)>,
+    path: &'p Path,
+    /// Field whose key was read and value was not yet
+    value: Option<(&'de Key, &'de Value<P>)>,
+}
+
+impl<'de, 'p, P> Fields<'de, 'p, P> {
+    fn value<T: DeserializeSeed<'de>>(
+        &self,
+        seed: T,
+        key: &Key,
+        value: &'de Value<P>,
+    ) -> Result<T::Value, DeError> {
+        let path = self.path.with(key);
+        seed.deserialize(ValueDeserializer { value, path: &path })
+            .map_err(|err| err.at(&path))
+    }
+}
+
+impl<'de, 'p, P> SeqAccess<'de> for Fields<'de, 'p, P> {
+    type Error = DeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DeError> {
+        match self.fields.next() {
+            Some((key, value)) => self.value(seed, key, value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+impl<'de, 'p, P> MapAccess<'de> for Fields<'de, 'p, P> {
+    type Error = DeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DeError> {
+        let Some((key, value)) = self.fields.next() else {
+            return Ok(None);
+        };
+        self.value = Some((key, value));
+        let name: StringDeserializer<DeError> = key_name(key).into_deserializer();
+        seed.deserialize(name).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeError> {
+        let (key, value) = self.value.take().expect("values are read after their keys");
+        self.value(seed, key, value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+/// Enum variant given as a record with a single field
+struct Variant<'de, P> {
+    name: String,
+    value: &'de Value<P>,
+    path: Path,
+}
+
+impl<'de, P> de::EnumAccess<'de> for Variant<'de, P> {
+    type Error = DeError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), DeError> {
+        let name: StringDeserializer<DeError> = self.name.clone().into_deserializer();
+        Ok((seed.deserialize(name)?, self))
+    }
+}
+
+impl<'de, P> de::VariantAccess<'de> for Variant<'de, P> {
+    type Error = DeError;
+
+    fn unit_variant(self) -> Result<(), DeError> {
+        Deserialize::deserialize(self.deserializer()).map_err(|err| err.at(&self.path))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, DeError> {
+        seed.deserialize(self.deserializer())
+            .map_err(|err| err.at(&self.path))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, DeError> {
+        de::Deserializer::deserialize_seq(self.deserializer(), visitor)
+            .map_err(|err| err.at(&self.path))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        de::Deserializer::deserialize_map(self.deserializer(), visitor)
+            .map_err(|err| err.at(&self.path))
+    }
+}
+
+impl<'de, P> Variant<'de, P> {
+    fn deserializer(&self) -> ValueDeserializer<'de, '_, P> {
+        ValueDeserializer {
+            value: self.value,
+            path: &self.path,
+        }
+    }
+}
+
+#[cfg(test)]
+use serde::Deserialize as Derive;
+
+#[cfg(test)]
+#[derive(Derive, Debug, PartialEq)]
+struct Config {
+    name: String,
+    server: Server,
+    tags: Vec<String>,
+    ratio: f64,
+    limit: Option<u32>,
+    backup: Option<Server>,
+    mode: Mode,
+    fallback: Mode,
+}
+
+#[cfg(test)]
+#[derive(Derive, Debug, PartialEq)]
+struct Server {
+    host: String,
+    port: u16,
+}
+
+#[cfg(test)]
+#[derive(Derive, Debug, PartialEq)]
+enum Mode {
+    Fast,
+    Limited { rate: i64 },
+}
+
+#[test]
+fn deserialize_config() {
+    let config: Config = from_str(
+        "(defaults = (port = 8080)).(
+            name = 'api',
+            server = (host = 'localhost', port = defaults.port),
+            tags = ('a', 'b'),
+            ratio = 1,
+            backup = (),
+            mode = 'Fast',
+            fallback = (Limited = (rate = 10)),
+        )",
+    )
+    .unwrap();
+    let expected = Config {
+        name: "api".to_string(),
+        server: Server {
+            host: "localhost".to_string(),
+            port: 8080,
+        },
+        tags: vec!["a".to_string(), "b".to_string()],
+        ratio: 1.0,
+        limit: None,
+        backup: None,
+        mode: Mode::Fast,
+        fallback: Mode::Limited { rate: 10 },
+    };
+    assert_eq!(config, expected);
+    let pair: (i64, String) = from_str("(1, 'one')").unwrap();
+    assert_eq!(pair, (1, "one".to_string()));
+}
+
+#[test]
+fn deserialize_errors() {
+    let err = from_str::<Server>("(host = 'h', port = 70000)").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .port: invalid value: integer `70000`, expected u16"
+    );
+    let err =
+        from_str::<Vec<Server>>("((host = 'h', port = 1), (host = 2, port = 1))").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .1.host: invalid type: integer `2`, expected a string"
+    );
+    let err = from_str::<Server>("(host = 'h')").unwrap_err();
+    assert_eq!(err.to_string(), "at .: missing field `port`");
+    let err = from_str::<Server>("(host = \\{} => 1, port = 1)").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .host: a function can not be deserialized"
+    );
+    let err = from_str::<Server>("(host = 'h', port = 'x' 1)").unwrap_err();
+    assert!(matches!(err, DeError::Type { .. }), "{err}");
+    assert!(matches!(
+        from_str::<Server>("(host ="),
+        Err(DeError::Parse(_))
+    ));
+}
@@ -0,0 +1,427 @@
+use std::fmt::Write;
+
+use pest::error::ErrorVariant;
+use serde_json::json;
+
+use crate::{
+    evaltime::{checking::TypeError, evaluate::EvalError, interpreter::Interpteter},
+    language::source::{Sources, Span},
+    parse::SyntaxError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// A report about a problem in kers sources
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Ansi,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        let primary = self.labels.is_empty();
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    fn with_label_at(self, span: Option<Span>, message: impl Into<String>) -> Self {
+        match span {
+            Some(span) => self.with_label(span, message),
+            None => self,
+        }
+    }
+
+    pub fn syntax(err: &SyntaxError) -> Self {
+        let span = err.span();
+        match err {
+            SyntaxError::ParseError { error, .. } => {
+                let label = match &error.variant {
+                    ErrorVariant::ParsingError { .. } => "unexpected input",
+                    ErrorVariant::CustomError { .. } => "here",
+                };
+                Diagnostic::error(format!("syntax error: {}", error.variant.message()))
+                    .with_label(span, label)
+            }
+            SyntaxError::ParseNumberError { source, .. } => {
+                Diagnostic::error("invalid number literal").with_label(span, source.to_string())
+            }
+            SyntaxError::CharError { text, .. } => Diagnostic::error("invalid character")
+                .with_label(span, format!("can not decode `{text}`")),
+            SyntaxError::Other { msg, .. } => {
+                Diagnostic::error(msg.clone()).with_label(span, msg.clone())
+            }
+        }
+    }
+
+    /// Diagnostic for the type error, `span` is location of the term the check failed on
+    pub fn typing<P: Interpteter>(err: &TypeError<P>, span: Option<Span>) -> Self {
+        match err {
+            TypeError::Mismatch(_, inferred, expected) => Diagnostic::error("mismatched types")
+                .with_label_at(span, format!("expected {expected}, found {inferred}")),
+            TypeError::UnboundName(key) => Diagnostic::error(format!("unbound name `{key}`"))
+                .with_label_at(span, "not found in the context"),
+            TypeError::NotARecord(_, typ) => Diagnostic::error("expected a record")
+                .with_label_at(span, format!("this has type {typ}")),
+            TypeError::NotAFunction(_, typ) => Diagnostic::error("expected a function")
+                .with_label_at(span, format!("this has type {typ}")),
+            TypeError::NotAType(_, typ) => Diagnostic::error("expected a type")
+                .with_label_at(span, format!("this is a value of type {typ}")),
+            TypeError::MissingField(_, key) => Diagnostic::error(format!("missing field `{key}`"))
+                .with_label_at(span, format!("field `{key}` is required here")),
+            TypeError::NotText(_, typ) => Diagnostic::error("can not interpolate into a text")
+                .with_label_at(span, format!("this has type {typ}"))
+                .with_note("only texts and numbers can be interpolated"),
+            TypeError::Occurs(_, typ) => Diagnostic::error("infinite type")
+                .with_label_at(span, format!("type would have to contain itself: {typ}")),
+            TypeError::UnresolvedImport(path) => {
+                Diagnostic::error(format!("unresolved import of {path}"))
+                    .with_label_at(span, "imports are resolved when loading files")
+            }
+            TypeError::Eval(err) => Diagnostic::evaluation(err, span)
+                .with_note("error occurred while evaluating a type"),
+        }
+    }
+
+    /// Diagnostic for the evaluation error, `span` is location of the term the evaluation failed on
+    pub fn evaluation(err: &EvalError, span: Option<Span>) -> Self {
+        match err {
+            EvalError::ValueIsNotAFunction { plugin_name, info } => {
+                Diagnostic::error("value is not a function")
+                    .with_label_at(span, format!("this is {info}"))
+                    .with_note(format!("reported by {plugin_name}"))
+            }
+            EvalError::UnboundName(key) => Diagnostic::error(format!("unbound name `{key}`"))
+                .with_label_at(span, "not found in the context"),
+            EvalError::NotARecord { info } => Diagnostic::error("value is not a record")
+                .with_label_at(span, format!("this is {info}")),
+            EvalError::NotAType { info } => Diagnostic::error("value is not a type")
+                .with_label_at(span, format!("this is {info}")),
+            EvalError::NotText { info } => Diagnostic::error("can not interpolate into a text")
+                .with_label_at(span, format!("this is {info}")),
+            EvalError::UnresolvedImport(path) => {
+                Diagnostic::error(format!("unresolved import of {path}"))
+                    .with_label_at(span, "imports are resolved when loading files")
+            }
+            EvalError::Intersection { left, right } => Diagnostic::error("can not intersect types")
+                .with_label_at(span, format!("intersection of {left} and {right}")),
+        }
+    }
+
+    /// Multi-line report with the source snippets, similar to the ones rustc prints
+    pub fn render(&self, sources: &Sources, style: Style) -> String {
+        let paint = Paint(style);
+        let mut out = String::new();
+        let (severity, color) = match self.severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        };
+        let _ = writeln!(
+            out,
+            "{}{}",
+            paint.apply(color, severity),
+            paint.apply(BOLD, &format!(": {}", self.message))
+        );
+
+        let gutter = self
+            .labels
+            .iter()
+            .map(|label| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let bar = paint.apply(BLUE, &format!("{:gutter$} |", ""));
+
+        let mut labels: Vec<_> = self.labels.iter().collect();
+        labels.sort_by_key(|label| (!label.primary, label.span.file.0, label.span.start));
+        let mut shown_line = None;
+        for label in labels {
+            let span = label.span;
+            let source = sources.get(span.file);
+            if shown_line.is_none_or(|(file, _)| file != span.file) {
+                let name = source.map_or("<unknown>", |source| source.name.as_str());
+                let arrow = paint.apply(BLUE, &format!("{:gutter$}-->", ""));
+                let _ = writeln!(out, "{arrow} {name}:{}:{}", span.line, span.col);
+                let _ = writeln!(out, "{bar}");
+            }
+            // the line after the trailing newline is empty, errors at the end of the input point there
+            let line = source.and_then(|source| source.text.split('\n').nth(span.line - 1));
+            let Some(text) = line else {
+                shown_line = Some((span.file, 0));
+                continue;
+            };
+            if shown_line != Some((span.file, span.line)) {
+                let number = paint.apply(BLUE, &format!("{:>gutter$} |", span.line));
+                let _ = writeln!(out, "{number} {}", text.trim_end_matches('\r'));
+            }
+            shown_line = Some((span.file, span.line));
+
+            let width = source.map_or(1, |source| {
+                let rest = source.text.get(span.start..).unwrap_or("");
+                let line_end = span.start + rest.find('\n').unwrap_or(rest.len());
+                let end = span.end.clamp(span.start, line_end);
+                source.text[span.start..end].chars().count().max(1)
+            });
+            let (mark, color) = if label.primary {
+                ('^', RED)
+            } else {
+                ('-', BLUE)
+            };
+            let marks: String = std::iter::repeat_n(mark, width).collect();
+            let pointer = format!(
+                "{:indent$}{marks} {}",
+                "",
+                label.message,
+                indent = span.col - 1
+            );
+            let _ = writeln!(out, "{bar} {}", paint.apply(color, pointer.trim_end()));
+        }
+
+        if !self.labels.is_empty() && !self.notes.is_empty() {
+            let _ = writeln!(out, "{bar}");
+        }
+        for note in &self.notes {
+            let eq = paint.apply(BLUE, &format!("{:gutter$} =", ""));
+            let _ = writeln!(out, "{eq} {}: {note}", paint.apply(BOLD, "note"));
+        }
+        out
+    }
+
+    /// Machine readable form of the diagnostic
+    pub fn to_json(&self, sources: &Sources) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let labels: Vec<_> = self
+            .labels
+            .iter()
+            .map(|label| {
+                let span = label.span;
+                let file = sources
+                    .get(span.file)
+                    .map_or("<unknown>", |source| source.name.as_str());
+                json!({
+                    "file": file,
+                    "start": span.start,
+                    "end": span.end,
+                    "line": span.line,
+                    "column": span.col,
+                    "message": label.message,
+                    "primary": label.primary,
+                })
+            })
+            .collect();
+        let diagnostic = json!({
+            "severity": severity,
+            "message": self.message,
+            "labels": labels,
+            "notes": self.notes,
+        });
+        diagnostic.to_string()
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+struct Paint(Style);
+
+impl Paint {
+    fn apply(&self, color: &str, text: &str) -> String {
+        match self.0 {
+            Style::Plain => text.to_string(),
+            Style::Ansi => format!("{color}{text}{RESET}"),
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::{
+    evaltime::{checking::TypeChecking, evaluate::Evaluation, values::Value},
+    parse::parse_source,
+};
+
+#[test]
+fn render_syntax_error() {
+    let mut sources = Sources::default();
+    let input = "(a = 1,\n b = 99999999999999999999)";
+    let file = sources.add("config.kers", input);
+    let err = parse_source(input, file).unwrap_err();
+    let rendered = Diagnostic::syntax(&err).render(&sources, Style::Plain);
+    let expected = "\
+error: invalid number literal
+ --> config.kers:2:6
+  |
+2 |  b = 99999999999999999999)
+  |      ^^^^^^^^^^^^^^^^^^^^ number too large to fit in target type
+";
+    assert_eq!(rendered, expected);
+}
+
+#[test]
+fn render_end_of_input() {
+    let mut sources = Sources::default();
+    let input = "(a = \n";
+    let file = sources.add("syn.kers", input);
+    let err = parse_source(input, file).unwrap_err();
+    let rendered = Diagnostic::syntax(&err).render(&sources, Style::Plain);
+    let expected = "\
+error: syntax error: expected term
+ --> syn.kers:2:1
+  |
+2 | 
+  | ^ unexpected input
+";
+    assert_eq!(rendered, expected);
+}
+
+#[test]
+fn render_type_error() {
+    let mut sources = Sources::default();
+    let input = "(f = \\{x: #int} => x).f (x = 'one')";
+    let file = sources.add("app.kers", input);
+    let (term, spans) = parse_source(input, file).unwrap();
+    let mut checking = TypeChecking::new(());
+    let err = checking.check(&term, &Value::empty()).unwrap_err();
+    let span = checking.failed_at().and_then(|node| spans.get_node(node));
+    let rendered = Diagnostic::typing(&err, span).render(&sources, Style::Plain);
+    let expected = "\
+error: mismatched types
+ --> app.kers:1:26
+  |
+1 | (f = \\{x: #int} => x).f (x = 'one')
+  |                          ^^^^^^^^^ expected #int, found #text
+";
+    assert_eq!(rendered, expected);
+}
+
+#[test]
+fn render_eval_error_with_notes() {
+    let mut sources = Sources::default();
+    let input = "(a = 1,\n b = (c = 2).@d)";
+    let file = sources.add("main.kers", input);
+    let (term, spans) = parse_source(input, file).unwrap();
+    let mut evaluation = Evaluation::new(());
+    let context = Value::Record {
+        fields: vec![("d".to_string().into(), Value::empty())],
+    };
+    let err = evaluation.eval(&term, &context).unwrap_err();
+    let span = evaluation.failed_at().and_then(|node| spans.get_node(node));
+    let diagnostic = Diagnostic::evaluation(&err, span);
+    let rendered = diagnostic.render(&sources, Style::Plain);
+    let expected = "\
+error: value is not a function
+ --> main.kers:2:15
+  |
+2 |  b = (c = 2).@d)
+  |               ^ this is ()
+  |
+  = note: reported by core
+";
+    assert_eq!(rendered, expected);
+
+    let colored = diagnostic.render(&sources, Style::Ansi);
+    assert!(colored.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: value is not a function\x1b[0m\n"));
+    assert_eq!(strip_ansi(&colored), rendered);
+}
+
+#[cfg(test)]
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[test]
+fn render_multiple_labels() {
+    let mut sources = Sources::default();
+    let input = "(a = 1,\n b = a)";
+    let file = sources.add("two.kers", input);
+    let span = |start, end, line, col| Span {
+        file,
+        start,
+        end,
+        line,
+        col,
+    };
+    let diagnostic = Diagnostic::error("duplicate")
+        .with_label(span(13, 14, 2, 6), "used here")
+        .with_label(span(1, 2, 1, 2), "defined here");
+    let expected = "\
+error: duplicate
+ --> two.kers:2:6
+  |
+2 |  b = a)
+  |      ^ used here
+1 | (a = 1,
+  |  - defined here
+";
+    assert_eq!(diagnostic.render(&sources, Style::Plain), expected);
+}
+
+#[test]
+fn diagnostic_to_json() {
+    let mut sources = Sources::default();
+    let file = sources.add("dir/\"q\".kers", "x");
+    let span = Span {
+        file,
+        start: 0,
+        end: 1,
+        line: 1,
+        col: 1,
+    };
+    let diagnostic = Diagnostic::error("unbound name `x`")
+        .with_label(span, "not found\tin the context")
+        .with_note("a note");
+    assert_eq!(
+        diagnostic.to_json(&sources),
+        r#"{"severity":"error","message":"unbound name `x`","labels":[{"file":"dir/\"q\".kers","start":0,"end":1,"line":1,"column":1,"message":"not found\tin the context","primary":true}],"notes":["a note"]}"#
+    );
+}
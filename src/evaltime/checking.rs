@@ -1,57 +1,738 @@
-use crate::Term;
+use std::sync::Arc;
 
-use super::{interpreter::Interpteter, values::Value, variables::VarIdx};
+use thiserror::Error;
 
+use crate::{language::source::NodeId, GenType, Key, PrimType, Primitive, Term, Type};
+
+use super::{
+    evaluate::{EvalError, Evaluation, Imports},
+    interpreter::Interpteter,
+    values::{merge_fields, Scope, TypeValue, Value},
+    variables::{VarIdx, Variables},
+};
+
+#[derive(Error, Debug, PartialEq)]
 pub enum TypeError<P: Interpteter> {
-    #[allow(unused)]
+    #[error("Type mismatch in {0}: inferred {1}, expected {2}")]
     Mismatch(Term, Value<P>, Value<P>),
+    #[error("Unbound name {0}")]
+    UnboundName(Key),
+    #[error("Expected a record in {0}, got {1}")]
+    NotARecord(Term, Value<P>),
+    #[error("Expected a function in {0}, got {1}")]
+    NotAFunction(Term, Value<P>),
+    #[error("Expected a type in {0}, got value of type {1}")]
+    NotAType(Term, Value<P>),
+    #[error("Missing field {1} in {0}")]
+    MissingField(Term, Key),
+    #[error("Expected a text or a number in {0}, got {1}")]
+    NotText(Term, Value<P>),
+    #[error("Infinite type: ?{0:?} occurs in {1}")]
+    Occurs(VarIdx, Value<P>),
+    #[error("Can not evaluate type: {0}")]
+    Eval(#[from] EvalError),
+    #[error("Import of {0} is not resolved")]
+    UnresolvedImport(String),
 }
 
+type Checked<P> = Result<Value<P>, TypeError<P>>;
+
 #[derive(Debug, Default)]
-pub(crate) struct TypeChecking<P> {
-    #[allow(unused)]
+pub struct TypeChecking<P> {
     plugins: P,
+    variables: Variables<P>,
+    imports: Arc<Imports<P>>,
+    failed_at: Option<NodeId>,
 }
 
 impl<P: Interpteter> TypeChecking<P> {
-    #[allow(unused)]
-    pub fn new() -> Self {
-        todo!("new")
+    pub fn new(plugins: P) -> Self {
+        TypeChecking {
+            plugins,
+            variables: Variables::default(),
+            imports: Arc::default(),
+            failed_at: None,
+        }
     }
 
-    #[allow(unused)]
-    pub fn new_var(&mut self) -> VarIdx {
-        todo!("new_var")
+    /// Types `import` terms as the imported files, type terms are evaluated with their values
+    pub fn with_imports(mut self, imports: Arc<Imports<P>>) -> Self {
+        self.imports = imports;
+        self
     }
 
-    #[allow(unused)]
-    fn unify<'a>(
+    /// The innermost term the last check failed on
+    pub fn failed_at(&self) -> Option<NodeId> {
+        self.failed_at
+    }
+
+    fn located<T>(
         &mut self,
-        inferred: Value<P>,
-        expected: Value<P>,
-    ) -> Result<Value<P>, TypeError<P>> {
-        todo!("unify")
+        term: &Term,
+        result: Result<T, TypeError<P>>,
+    ) -> Result<T, TypeError<P>> {
+        if result.is_err() && self.failed_at.is_none() {
+            self.failed_at = Some(NodeId::of(term));
+        }
+        result
+    }
+
+    pub fn new_var(&mut self) -> VarIdx {
+        self.variables.fresh()
+    }
+
+    /// Type with all the solved variables substituted
+    pub fn zonk(&mut self, typ: Value<P>) -> Value<P> {
+        self.variables.zonk(typ)
+    }
+
+    fn solve(&mut self, idx: VarIdx, value: Value<P>) -> Checked<P> {
+        match self.variables.solve(idx, value.clone()) {
+            Ok(()) => Ok(value),
+            Err(value) => Err(TypeError::Occurs(idx, value)),
+        }
+    }
+
+    /// Checks that `inferred` type can be used where `expected` type is required
+    fn unify(&mut self, term: &Term, inferred: Value<P>, expected: Value<P>) -> Checked<P> {
+        use TypeValue::{Function, Prim, Record};
+        let mismatch = |inferred, expected| TypeError::Mismatch(term.clone(), inferred, expected);
+        let inferred = self.variables.resolve(inferred);
+        let expected = self.variables.resolve(expected);
+        match (inferred, expected) {
+            (Value::Type(Prim(inferred)), Value::Type(Prim(expected))) if inferred == expected => {
+                Ok(Prim(expected).into())
+            }
+            (Value::Variable(inferred), Value::Variable(expected)) if inferred == expected => {
+                Ok(Value::Variable(expected))
+            }
+            (Value::Variable(idx), expected) => self.solve(idx, expected),
+            (inferred, Value::Variable(idx)) => self.solve(idx, inferred),
+            (_, expected @ Value::Type(Prim(PrimType::Any))) => Ok(expected),
+            (
+                Value::Type(Record { fields: inferred }),
+                Value::Type(Record { fields: expected }),
+            ) => {
+                let mut unified = Vec::with_capacity(expected.len());
+                for (name, typ) in expected {
+                    let Some((_, field)) = inferred.iter().find(|(key, _)| *key == name) else {
+                        return Err(TypeError::MissingField(term.clone(), name));
+                    };
+                    unified.push((name, self.unify(term, field.clone(), typ)?));
+                }
+                Ok(Value::record_type(unified))
+            }
+            (
+                Value::Type(Function {
+                    dom: dom_i,
+                    codom: codom_i,
+                }),
+                Value::Type(Function {
+                    dom: dom_e,
+                    codom: codom_e,
+                }),
+            ) => {
+                self.unify(term, *dom_e.clone(), *dom_i)?;
+                self.unify(term, *codom_i, *codom_e.clone())?;
+                Ok(Function {
+                    dom: dom_e,
+                    codom: codom_e,
+                }
+                .into())
+            }
+            (inferred, expected) => Err(mismatch(inferred, expected)),
+        }
+    }
+
+    /// Infers type of the `term`, `context` is the type of the record it is evaluated in
+    pub fn check(&mut self, term: &Term, context: &Value<P>) -> Checked<P> {
+        self.failed_at = None;
+        let typ = self.infer(term, &Scope::root(context.clone()))?;
+        Ok(self.zonk(typ))
     }
 
-    #[allow(unused)]
-    pub fn check<'a>(
-        &'a mut self,
+    /// Checks that `term` has the `expected` type, `context` is the type of the record it is evaluated in
+    pub fn check_against(
+        &mut self,
         term: &Term,
         context: &Value<P>,
-    ) -> Result<Value<P>, TypeError<P>> {
+        expected: &Value<P>,
+    ) -> Checked<P> {
+        self.failed_at = None;
+        let typ = self.check_in(term, &Scope::root(context.clone()), expected.clone())?;
+        Ok(self.zonk(typ))
+    }
+
+    fn infer(&mut self, term: &Term, scope: &Scope<P>) -> Checked<P> {
+        let result = self.infer_term(term, scope);
+        self.located(term, result)
+    }
+
+    fn infer_term(&mut self, term: &Term, scope: &Scope<P>) -> Checked<P> {
         match term {
-            Term::Empty => Ok(Value::Record { fields: vec![] }),
-            Term::Reflect => {
-                let cloned: Value<P> = context.clone();
-                Ok(context.clone())
+            Term::Prim(Primitive::Long(_)) => Ok(TypeValue::Prim(PrimType::Long).into()),
+            Term::Prim(Primitive::Float(_)) => Ok(TypeValue::Prim(PrimType::Float).into()),
+            Term::Prim(Primitive::Text(_)) => Ok(TypeValue::Prim(PrimType::Text).into()),
+            Term::Type(typ) => {
+                self.check_type_formation(typ, scope)?;
+                Ok(TypeValue::Prim(PrimType::Universe).into())
+            }
+            Term::Empty => Ok(Value::record_type(vec![])),
+            Term::Set { name, value } => {
+                let typ = self.infer(value, scope)?;
+                Ok(Value::record_type(vec![(name.clone(), typ)]))
             }
             Term::Append { left, right } => {
-                let left = self.check(left, context)?;
-                let right = self.check(right, context)?;
-                todo!()
+                let mut fields = self.record_fields(left, scope)?;
+                merge_fields(&mut fields, self.record_fields(right, scope)?);
+                Ok(Value::record_type(fields))
+            }
+            Term::Get(key) => scope
+                .lookup_type(key)
+                .cloned()
+                .ok_or_else(|| TypeError::UnboundName(key.clone())),
+            Term::Then { first, next } => {
+                let context = self.infer(first, scope)?;
+                let context = self.zonk(context);
+                self.infer(next, &scope.chain(context))
+            }
+            Term::Let { name, value, body } => {
+                let scope = self.binding(name, value, scope)?;
+                self.infer(body, &scope)
+            }
+            Term::Lambda { dom, body } => {
+                let dom = self.eval_type(dom, scope)?;
+                let codom = self.infer(body, &scope.push(dom.clone()))?;
+                Ok(TypeValue::Function {
+                    dom: Box::new(dom),
+                    codom: Box::new(codom),
+                }
+                .into())
+            }
+            Term::Unlambda(func) => {
+                let (dom, codom) = self.function(func, &scope.outer())?;
+                let argument = current(scope);
+                self.unify(term, argument, dom)?;
+                Ok(codom)
+            }
+            Term::Reflect => Ok(current(scope)),
+            Term::Import { path, .. } => self
+                .imports
+                .typ(NodeId::of(term))
+                .cloned()
+                .ok_or_else(|| TypeError::UnresolvedImport(path.clone())),
+            Term::Interpolate(parts) => {
+                for part in parts {
+                    let typ = self.infer(part, scope)?;
+                    let result = self.interpolated(part, typ);
+                    self.located(part, result)?;
+                }
+                Ok(TypeValue::Prim(PrimType::Text).into())
+            }
+        }
+    }
+
+    fn check_in(&mut self, term: &Term, scope: &Scope<P>, expected: Value<P>) -> Checked<P> {
+        let result = self.check_term(term, scope, expected);
+        self.located(term, result)
+    }
+
+    fn check_term(&mut self, term: &Term, scope: &Scope<P>, expected: Value<P>) -> Checked<P> {
+        match (term, self.variables.resolve(expected)) {
+            (
+                Term::Lambda { dom, body },
+                Value::Type(TypeValue::Function {
+                    dom: expected_dom,
+                    codom,
+                }),
+            ) => {
+                let dom = self.eval_type(dom, scope)?;
+                self.unify(term, *expected_dom.clone(), dom.clone())?;
+                self.check_in(body, &scope.push(dom), *codom.clone())?;
+                Ok(TypeValue::Function {
+                    dom: expected_dom,
+                    codom,
+                }
+                .into())
+            }
+            (Term::Then { first, next }, expected) => {
+                let context = self.infer(first, scope)?;
+                let context = self.zonk(context);
+                self.check_in(next, &scope.chain(context), expected)
+            }
+            (Term::Let { name, value, body }, expected) => {
+                let scope = self.binding(name, value, scope)?;
+                self.check_in(body, &scope, expected)
+            }
+            (Term::Set { name, value }, Value::Type(TypeValue::Record { fields }))
+                if fields.len() == 1 && fields[0].0 == *name =>
+            {
+                let typ = self.check_in(value, scope, fields[0].1.clone())?;
+                Ok(Value::record_type(vec![(name.clone(), typ)]))
+            }
+            (term, expected) => {
+                let inferred = self.infer(term, scope)?;
+                self.unify(term, inferred, expected)
+            }
+        }
+    }
+
+    fn check_type_formation(&mut self, typ: &Type, scope: &Scope<P>) -> Result<(), TypeError<P>> {
+        match typ {
+            GenType::Prim(_) => Ok(()),
+            GenType::Field { typ, .. } => self.check_is_type(typ, scope),
+            GenType::Function { dom, codom } => {
+                self.check_is_type(dom, scope)?;
+                self.check_is_type(codom, scope)
+            }
+            GenType::And { left, right } => {
+                self.check_is_type(left, scope)?;
+                self.check_is_type(right, scope)
+            }
+        }
+    }
+
+    fn check_is_type(&mut self, term: &Term, scope: &Scope<P>) -> Result<(), TypeError<P>> {
+        let result = match self.infer(term, scope)? {
+            Value::Type(TypeValue::Prim(PrimType::Universe)) => Ok(()),
+            other => Err(TypeError::NotAType(term.clone(), other)),
+        };
+        self.located(term, result)
+    }
+
+    /// Type terms are evaluated in an empty context, seeing the types bound by `let`
+    fn eval_type(&mut self, term: &Term, scope: &Scope<P>) -> Checked<P> {
+        self.check_is_type(term, scope)?;
+        self.evaluate(term, scope).map_err(|(err, at)| {
+            self.failed_at = self.failed_at.or(at);
+            err.into()
+        })
+    }
+
+    /// Value of the term, with the node the evaluation failed on
+    fn evaluate(
+        &self,
+        term: &Term,
+        scope: &Scope<P>,
+    ) -> Result<Value<P>, (EvalError, Option<NodeId>)> {
+        let mut evaluation =
+            Evaluation::new(self.plugins.clone()).with_imports(self.imports.clone());
+        evaluation
+            .eval_in(term, &scope.definitions())
+            .map_err(|err| (err, evaluation.failed_at()))
+    }
+
+    fn record_fields(
+        &mut self,
+        term: &Term,
+        scope: &Scope<P>,
+    ) -> Result<Vec<(Key, Value<P>)>, TypeError<P>> {
+        let result = match self.infer(term, scope)? {
+            Value::Type(TypeValue::Record { fields }) => Ok(fields),
+            other => Err(TypeError::NotARecord(term.clone(), other)),
+        };
+        self.located(term, result)
+    }
+
+    /// Scope of the `let` body, with the name bound to the type of the value,
+    /// types are also bound to themselves to be used in the domains of lambdas
+    fn binding(
+        &mut self,
+        name: &Key,
+        value: &Term,
+        scope: &Scope<P>,
+    ) -> Result<Scope<P>, TypeError<P>> {
+        let typ = self.infer(value, scope)?;
+        let typ = self.zonk(typ);
+        let is_type = matches!(typ, Value::Type(TypeValue::Prim(PrimType::Universe)));
+        let types = Value::record_type(vec![(name.clone(), typ)]);
+        // types depending on the arguments of lambdas have no value yet
+        match is_type.then(|| self.evaluate(value, scope).ok()).flatten() {
+            Some(value) => {
+                let values = Value::Record {
+                    fields: vec![(name.clone(), value)],
+                };
+                Ok(scope.define(types, values))
+            }
+            None => Ok(scope.bind(types)),
+        }
+    }
+
+    /// Checks that a value of type `typ` has a text conversion, unsolved types are taken to be texts
+    fn interpolated(&mut self, part: &Term, typ: Value<P>) -> Checked<P> {
+        use PrimType::{Float, Long, Text};
+        match self.variables.resolve(typ) {
+            typ @ Value::Type(TypeValue::Prim(Text | Long | Float)) => Ok(typ),
+            Value::Variable(idx) => self.solve(idx, TypeValue::Prim(Text).into()),
+            other => {
+                let err = Err(TypeError::NotText(part.clone(), other));
+                self.located(part, err)
             }
+        }
+    }
 
-            _ => todo!("type_check"),
+    fn function(
+        &mut self,
+        term: &Term,
+        scope: &Scope<P>,
+    ) -> Result<(Value<P>, Value<P>), TypeError<P>> {
+        let typ = self.infer(term, scope)?;
+        match self.variables.resolve(typ) {
+            Value::Type(TypeValue::Function { dom, codom }) => Ok((*dom, *codom)),
+            Value::Variable(idx) => {
+                let dom = Value::Variable(self.new_var());
+                let codom = Value::Variable(self.new_var());
+                let function = TypeValue::Function {
+                    dom: Box::new(dom.clone()),
+                    codom: Box::new(codom.clone()),
+                };
+                self.solve(idx, function.into())?;
+                Ok((dom, codom))
+            }
+            other => {
+                let err = Err(TypeError::NotAFunction(term.clone(), other));
+                self.located(term, err)
+            }
         }
     }
 }
+
+fn current<P: Clone>(scope: &Scope<P>) -> Value<P> {
+    scope
+        .current()
+        .cloned()
+        .unwrap_or_else(|| Value::record_type(vec![]))
+}
+
+#[cfg(test)]
+use crate::parse::parse_term;
+
+#[cfg(test)]
+fn infer_with(input: &str, context: &str) -> Checked<()> {
+    let context = Evaluation::new(()).eval(&parse_term(context).unwrap(), &Value::empty())?;
+    TypeChecking::new(()).check(&parse_term(input).unwrap(), &context)
+}
+
+#[cfg(test)]
+fn infer_str(input: &str) -> Checked<()> {
+    infer_with(input, "{}")
+}
+
+#[cfg(test)]
+fn type_of(input: &str) -> Value<()> {
+    Evaluation::new(())
+        .eval(&parse_term(input).unwrap(), &Value::empty())
+        .unwrap()
+}
+
+#[test]
+fn infer_primitives() {
+    assert_eq!(infer_str("38"), Ok(type_of("#int")));
+    assert_eq!(infer_str("'Hello'"), Ok(type_of("#text")));
+    assert_eq!(infer_str("()"), Ok(Value::record_type(vec![])));
+}
+
+#[test]
+fn infer_record() {
+    let res = infer_str("(greet = 'Hello', size = 3, greet = 4)");
+    assert_eq!(res, Ok(type_of("{greet: #int, size: #int}")));
+}
+
+#[test]
+fn infer_types() {
+    assert_eq!(infer_str("{a: #int} & {b: #text}"), Ok(type_of("*")));
+    assert_eq!(infer_str("#int -> #text"), Ok(type_of("*")));
+    assert_eq!(
+        infer_str("{a: 1}"),
+        Err(TypeError::NotAType(
+            Term::Prim(Primitive::Long(1)),
+            type_of("#int")
+        ))
+    );
+    let err = infer_with("(x = 'a').@f", "{f: {x: #int} -> #int}").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Type mismatch in @f: inferred #text, expected #int"
+    );
+}
+
+#[test]
+fn infer_from_context() {
+    assert_eq!(infer_with("x", "{x: #int}"), Ok(type_of("#int")));
+    assert_eq!(infer_with("@@", "{x: #int}"), Ok(type_of("{x: #int}")));
+    assert_eq!(
+        infer_with("y", "{x: #int}"),
+        Err(TypeError::UnboundName("y".to_string().into()))
+    );
+}
+
+#[test]
+fn infer_then_chain() {
+    assert_eq!(
+        infer_str("(a = 1, b = (c = 'deep')).b.c"),
+        Ok(type_of("#text"))
+    );
+    assert_eq!(
+        infer_with("(a = 1).(b = a)", "{x: #text}"),
+        Ok(type_of("{b: #int}"))
+    );
+    assert_eq!(
+        infer_with("(a = 1).(b = a, c = x)", "{x: #text}"),
+        Err(TypeError::UnboundName("x".to_string().into()))
+    );
+    assert_eq!(
+        infer_str("let a = 1 in (b = 2).a"),
+        Err(TypeError::UnboundName("a".to_string().into()))
+    );
+    assert_eq!(
+        infer_str("{a: #int}.a"),
+        Err(TypeError::UnboundName("a".to_string().into()))
+    );
+}
+
+#[cfg(test)]
+fn lambda(dom: &str, body: &str) -> Term {
+    Term::Lambda {
+        dom: parse_term(dom).unwrap(),
+        body: parse_term(body).unwrap(),
+    }
+}
+
+#[test]
+fn infer_lambda() {
+    let term = lambda("{x: #int}", "(arg = x, outer = y)");
+    let res = TypeChecking::new(()).check(&term, &type_of("{y: #text}"));
+    assert_eq!(res, Ok(type_of("{x: #int} -> {arg: #int, outer: #text}")));
+}
+
+#[test]
+fn infer_application() {
+    let context = type_of("{f: {x: #int} -> #text, n: #int}");
+    let mut checking = TypeChecking::new(());
+    let res = checking.check(&parse_term("f (x = 1, y = 2)").unwrap(), &context);
+    assert_eq!(res, Ok(type_of("#text")));
+
+    let res = checking.check(&parse_term("f (y = 2)").unwrap(), &context);
+    assert!(matches!(res, Err(TypeError::MissingField(_, _))));
+
+    let res = checking.check(&parse_term("f (x = 'one')").unwrap(), &context);
+    assert!(matches!(res, Err(TypeError::Mismatch(_, _, _))));
+
+    let res = checking.check(&parse_term("(x = 1).@n").unwrap(), &context);
+    assert_eq!(
+        res,
+        Err(TypeError::NotAFunction(Term::get("n"), type_of("#int")))
+    );
+    // the applied function is not looked up in the argument
+    let res = checking.check(&parse_term("(x = 1, g = f).@g").unwrap(), &context);
+    assert_eq!(res, Err(TypeError::UnboundName("g".to_string().into())));
+}
+
+#[test]
+fn check_lambda_against_function_type() {
+    let term = lambda("{x: #int}", "(arg = x)");
+    let mut checking = TypeChecking::new(());
+    let expected = type_of("{x: #int, y: #text} -> {arg: #int}");
+    let res = checking.check_against(&term, &Value::record_type(vec![]), &expected);
+    assert_eq!(res, Ok(expected));
+
+    let expected = type_of("{y: #text} -> {arg: #int}");
+    let res = checking.check_against(&term, &Value::record_type(vec![]), &expected);
+    assert!(matches!(res, Err(TypeError::MissingField(_, _))));
+
+    let expected = type_of("{x: #int} -> {arg: #text}");
+    let res = checking.check_against(&term, &Value::record_type(vec![]), &expected);
+    assert!(matches!(res, Err(TypeError::Mismatch(_, _, _))));
+}
+
+#[test]
+fn check_record_against_type() {
+    let mut checking = TypeChecking::new(());
+    let term = parse_term("(a = 1, b = 'x')").unwrap();
+    let res = checking.check_against(&term, &Value::record_type(vec![]), &type_of("{a: #int}"));
+    assert_eq!(res, Ok(type_of("{a: #int}")));
+
+    let res = checking.check_against(&term, &Value::record_type(vec![]), &type_of("{c: #int}"));
+    assert!(matches!(res, Err(TypeError::MissingField(_, _))));
+
+    let term = parse_term("(a = 1)").unwrap();
+    let res = checking.check_against(&term, &Value::record_type(vec![]), &type_of("{a: #text}"));
+    assert!(matches!(res, Err(TypeError::Mismatch(_, _, _))));
+}
+
+#[test]
+fn check_append_of_non_record() {
+    let term = Term::Append {
+        left: Term::Empty.into(),
+        right: Term::Prim(Primitive::Long(2)).into(),
+    };
+    let mut checking = TypeChecking::new(());
+    let res = checking.check(&term, &Value::record_type(vec![]));
+    assert_eq!(
+        res,
+        Err(TypeError::NotARecord(
+            Term::Prim(Primitive::Long(2)),
+            type_of("#int")
+        ))
+    );
+    let Term::Append { right, .. } = &term else {
+        unreachable!()
+    };
+    assert_eq!(checking.failed_at(), Some(NodeId::of(right)));
+}
+
+#[test]
+fn errors_are_located_at_the_operands() {
+    let cases = [
+        ("{a: #int} & 1", "1"),
+        ("{a: 1}", "1"),
+        ("#int -> 'a'", "'a'"),
+        ("\"${(a = 1)}\"", "(a = 1)"),
+    ];
+    for (input, operand) in cases {
+        let term = parse_term(input).unwrap();
+        let mut checking = TypeChecking::new(());
+        assert!(checking.check(&term, &Value::record_type(vec![])).is_err());
+        let operand = parse_term(operand).unwrap();
+        let at = checking.failed_at().unwrap();
+        assert!(
+            find(&term, &|node| NodeId::of(node) == at && *node == *operand),
+            "{input}"
+        );
+    }
+}
+
+#[cfg(test)]
+fn find(term: &Arc<Term>, found: &impl Fn(&Term) -> bool) -> bool {
+    found(term) || term.children().into_iter().any(|child| find(child, found))
+}
+
+#[test]
+fn holes_are_solved_by_application() {
+    let mut checking = TypeChecking::new(());
+    let func = checking.new_var();
+    let context = Value::record_type(vec![("f".to_string().into(), Value::Variable(func))]);
+
+    let term = parse_term("f (x = 1)").unwrap();
+    let res = checking.check_against(&term, &context, &type_of("#text"));
+    assert_eq!(res, Ok(type_of("#text")));
+    let solved = checking.zonk(Value::Variable(func));
+    assert_eq!(solved, type_of("{x: #int} -> #text"));
+}
+
+#[test]
+fn holes_in_record_types() {
+    let mut checking = TypeChecking::new(());
+    let hole = checking.new_var();
+    let expected = Value::record_type(vec![("a".to_string().into(), Value::Variable(hole))]);
+    let term = parse_term("(a = 'x', b = 2)").unwrap();
+    let res = checking.check_against(&term, &Value::record_type(vec![]), &expected);
+    assert_eq!(res, Ok(type_of("{a: #text}")));
+    assert_eq!(checking.zonk(Value::Variable(hole)), type_of("#text"));
+
+    let term = parse_term("(a = 3)").unwrap();
+    let res = checking.check_against(&term, &Value::record_type(vec![]), &expected);
+    assert!(matches!(res, Err(TypeError::Mismatch(_, _, _))));
+}
+
+#[test]
+fn holes_occurs_check() {
+    let mut checking = TypeChecking::new(());
+    let hole = checking.new_var();
+    let context = Value::record_type(vec![("f".to_string().into(), Value::Variable(hole))]);
+    let res = checking.check(&parse_term("f f").unwrap(), &context);
+    assert!(matches!(res, Err(TypeError::Occurs(_, _))));
+}
+
+#[test]
+fn infer_lambda_syntax() {
+    let res = infer_with(
+        "(f = \\{x: #int} => (sum = x, y = y)).f (x = 1)",
+        "{y: #text}",
+    );
+    assert_eq!(res, Ok(type_of("{sum: #int, y: #text}")));
+
+    let res = infer_str("(\\{x: #int} => x) (x = 'one')");
+    assert!(matches!(res, Err(TypeError::Mismatch(_, _, _))));
+}
+
+#[test]
+fn infer_tuples() {
+    assert_eq!(infer_str("(1, 'two')"), Ok(type_of("{#int, #text}")));
+    assert_eq!(infer_str("(1, ('two', 3)).1.0"), Ok(type_of("#text")));
+    assert_eq!(
+        infer_with("(\\{#int,} => @@.0) (5, 'extra')", "{}"),
+        Ok(type_of("#int"))
+    );
+    assert_eq!(type_of("{#int, #text}").to_string(), "{#int, #text}");
+}
+
+#[test]
+fn infer_numbers() {
+    assert_eq!(
+        infer_str("(zero = 0, neg = -0x10, pi = 3.14)"),
+        Ok(type_of("{zero: #int, neg: #int, pi: #float}"))
+    );
+    assert_eq!(
+        infer_with("(\\{x: #float} => x) (x = 1.0)", "{}"),
+        Ok(type_of("#float"))
+    );
+    assert!(matches!(
+        infer_with("(\\{x: #float} => x) (x = 1)", "{}"),
+        Err(TypeError::Mismatch(..))
+    ));
+}
+
+#[test]
+fn infer_interpolation() {
+    let context = "{target: #text, port: #int, ratio: #float, nested: {x: #int}}";
+    assert_eq!(
+        infer_with(r#""${target}:${port} ${ratio}""#, context),
+        Ok(type_of("#text"))
+    );
+    assert!(matches!(
+        infer_with(r#""nested: ${nested}""#, context),
+        Err(TypeError::NotText(..))
+    ));
+
+    let mut checking = TypeChecking::new(());
+    let hole = checking.new_var();
+    let context = Value::record_type(vec![("x".to_string().into(), Value::Variable(hole))]);
+    let term = parse_term(r#""x = ${x}""#).unwrap();
+    assert_eq!(checking.check(&term, &context), Ok(type_of("#text")));
+    assert_eq!(checking.zonk(Value::Variable(hole)), type_of("#text"));
+}
+
+#[test]
+fn infer_let() {
+    assert_eq!(
+        infer_with("let a = 1, b = (a = a, c = x) in (b = b)", "{x: #text}"),
+        Ok(type_of("{b: {a: #int, c: #text}}"))
+    );
+    assert_eq!(
+        infer_with("let a = 'a' in (@@, a)", "{x: #int}"),
+        Ok(type_of("{{x: #int}, #text}"))
+    );
+    assert_eq!(
+        infer_str("let a = 1 in b"),
+        Err(TypeError::UnboundName("b".to_string().into()))
+    );
+    assert_eq!(
+        infer_str("let T = {x: #int} in (f = \\T => x).f (x = 1)"),
+        Ok(type_of("#int"))
+    );
+    assert_eq!(
+        infer_with("let T = #int, U = {x: T} in \\U => x", "{T: #text}"),
+        Ok(type_of("{x: #int} -> #int"))
+    );
+    // the argument shadows the type, its value is not known while checking
+    assert!(matches!(
+        infer_str("let T = {x: #int} in \\{T: *} => \\T => 1"),
+        Err(TypeError::Eval(EvalError::UnboundName(_)))
+    ));
+    let mut checking = TypeChecking::new(());
+    let term = parse_term("let one = 1 in \\{x: #int} => one").unwrap();
+    let expected = type_of("{x: #int} -> #int");
+    let checked = checking.check_against(&term, &Value::record_type(vec![]), &expected);
+    assert_eq!(checked, Ok(expected));
+}
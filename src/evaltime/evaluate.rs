@@ -1,14 +1,536 @@
+use std::{collections::HashMap, sync::Arc};
+
 use thiserror::Error;
 
+use crate::{language::source::NodeId, GenType, Key, Primitive, Term, Type};
+
+use super::{
+    interpreter::Interpteter,
+    values::{merge_fields, Scope, TypeValue, Value},
+};
 
-#[allow(unused)]
-struct Evaluation<P> {
+pub struct Evaluation<P> {
+    #[allow(unused)]
     plugins: P,
+    imports: Arc<Imports<P>>,
+    failed_at: Option<NodeId>,
+}
+
+/// Types and values of the imported files, by the `import` terms referring to them
+#[derive(Debug, Clone)]
+pub struct Imports<P> {
+    types: HashMap<NodeId, Value<P>>,
+    values: HashMap<NodeId, Value<P>>,
+}
+
+impl<P> Default for Imports<P> {
+    fn default() -> Self {
+        Imports {
+            types: HashMap::new(),
+            values: HashMap::new(),
+        }
+    }
 }
 
-#[allow(unused)]
-#[derive(Error, Debug)]
-enum EvalError {
+impl<P> Imports<P> {
+    /// Resolves the `import` term with the id to the file with the type and the value
+    pub fn insert(&mut self, node: NodeId, typ: Value<P>, value: Value<P>) {
+        self.types.insert(node, typ);
+        self.values.insert(node, value);
+    }
+
+    pub fn typ(&self, node: NodeId) -> Option<&Value<P>> {
+        self.types.get(&node)
+    }
+
+    pub fn value(&self, node: NodeId) -> Option<&Value<P>> {
+        self.values.get(&node)
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum EvalError {
     #[error("Value is not a function, {info}")]
     ValueIsNotAFunction { plugin_name: String, info: String },
+    #[error("Unbound name {0}")]
+    UnboundName(Key),
+    #[error("Value is not a record, {info}")]
+    NotARecord { info: String },
+    #[error("Value is not a type, {info}")]
+    NotAType { info: String },
+    #[error("Can not intersect {left} and {right}")]
+    Intersection { left: String, right: String },
+    #[error("Value can not be interpolated into a text, {info}")]
+    NotText { info: String },
+    #[error("Import of {0} is not resolved")]
+    UnresolvedImport(String),
+}
+
+type Evaluated<P> = Result<Value<P>, EvalError>;
+
+const CORE: &str = "core";
+
+impl<P: Interpteter> Evaluation<P> {
+    pub fn new(plugins: P) -> Self {
+        Evaluation {
+            plugins,
+            imports: Arc::default(),
+            failed_at: None,
+        }
+    }
+
+    /// Evaluates `import` terms to the values of the imported files
+    pub fn with_imports(mut self, imports: Arc<Imports<P>>) -> Self {
+        self.imports = imports;
+        self
+    }
+
+    pub fn eval(&mut self, term: &Term, context: &Value<P>) -> Evaluated<P> {
+        self.failed_at = None;
+        self.eval_in(term, &Scope::root(context.clone()))
+    }
+
+    /// The innermost term the last evaluation failed on
+    pub fn failed_at(&self) -> Option<NodeId> {
+        self.failed_at
+    }
+
+    pub(crate) fn eval_in(&mut self, term: &Term, scope: &Scope<P>) -> Evaluated<P> {
+        let result = self.eval_term(term, scope);
+        if result.is_err() && self.failed_at.is_none() {
+            self.failed_at = Some(NodeId::of(term));
+        }
+        result
+    }
+
+    fn eval_term(&mut self, term: &Term, scope: &Scope<P>) -> Evaluated<P> {
+        match term {
+            Term::Prim(prim) => Ok(Value::Prim(prim.clone())),
+            Term::Type(typ) => Ok(Value::Type(self.eval_type(typ, scope)?)),
+            Term::Empty => Ok(Value::empty()),
+            Term::Set { name, value } => Ok(Value::Record {
+                fields: vec![(name.clone(), self.eval_in(value, scope)?)],
+            }),
+            Term::Append { left, right } => {
+                let mut fields = record_fields(self.eval_in(left, scope)?)?;
+                merge_fields(&mut fields, record_fields(self.eval_in(right, scope)?)?);
+                Ok(Value::Record { fields })
+            }
+            Term::Get(key) => scope
+                .lookup(key)
+                .cloned()
+                .ok_or_else(|| EvalError::UnboundName(key.clone())),
+            Term::Then { first, next } => {
+                let context = self.eval_in(first, scope)?;
+                self.eval_in(next, &scope.chain(context))
+            }
+            Term::Let { name, value, body } => {
+                let value = self.eval_in(value, scope)?;
+                let binding = Value::Record {
+                    fields: vec![(name.clone(), value)],
+                };
+                self.eval_in(body, &scope.bind(binding))
+            }
+            Term::Lambda { dom, body } => Ok(Value::Lambda {
+                dom: Box::new(self.eval_in(dom, scope)?.into_type()?.into()),
+                body: body.clone(),
+                scope: scope.clone(),
+            }),
+            Term::Unlambda(func) => {
+                let (body, closure) = match self.eval_in(func, &scope.outer())? {
+                    Value::Lambda { body, scope, .. } => (body, scope),
+                    other => {
+                        self.failed_at = Some(NodeId::of(func));
+                        return Err(EvalError::ValueIsNotAFunction {
+                            plugin_name: CORE.to_string(),
+                            info: other.to_string(),
+                        });
+                    }
+                };
+                let argument = scope.current().cloned().unwrap_or_else(Value::empty);
+                self.eval_in(&body, &closure.push(argument))
+            }
+            Term::Reflect => Ok(scope.current().cloned().unwrap_or_else(Value::empty)),
+            Term::Import { path, .. } => self
+                .imports
+                .value(NodeId::of(term))
+                .cloned()
+                .ok_or_else(|| EvalError::UnresolvedImport(path.clone())),
+            Term::Interpolate(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    text += &interpolated(self.eval_in(part, scope)?)?;
+                }
+                Ok(Value::Prim(Primitive::Text(text)))
+            }
+        }
+    }
+
+    fn eval_type(&mut self, typ: &Type, scope: &Scope<P>) -> Result<TypeValue<P>, EvalError> {
+        match typ {
+            GenType::Prim(prim) => Ok(TypeValue::Prim(prim.clone())),
+            GenType::Field { name, typ } => {
+                let typ = self.eval_in(typ, scope)?.into_type()?;
+                Ok(TypeValue::Record {
+                    fields: vec![(name.clone(), typ.into())],
+                })
+            }
+            GenType::Function { dom, codom } => Ok(TypeValue::Function {
+                dom: Box::new(self.eval_in(dom, scope)?.into_type()?.into()),
+                codom: Box::new(self.eval_in(codom, scope)?.into_type()?.into()),
+            }),
+            GenType::And { left, right } => {
+                let left = self.eval_in(left, scope)?.into_type()?;
+                let right = self.eval_in(right, scope)?.into_type()?;
+                intersect(left, right)
+            }
+        }
+    }
+}
+
+fn record_fields<P>(value: Value<P>) -> Result<Vec<(Key, Value<P>)>, EvalError> {
+    match value {
+        Value::Record { fields } => Ok(fields),
+        other => Err(EvalError::NotARecord {
+            info: other.to_string(),
+        }),
+    }
+}
+
+/// Text of a value inside of an interpolated string, numbers are converted to their literals
+fn interpolated<P>(value: Value<P>) -> Result<String, EvalError> {
+    match value {
+        Value::Prim(Primitive::Text(text)) => Ok(text),
+        Value::Prim(Primitive::Long(n)) => Ok(n.to_string()),
+        Value::Prim(Primitive::Float(x)) => Ok(x.to_string()),
+        other => Err(EvalError::NotText {
+            info: other.to_string(),
+        }),
+    }
+}
+
+fn intersect<P>(left: TypeValue<P>, right: TypeValue<P>) -> Result<TypeValue<P>, EvalError> {
+    use crate::PrimType::Any;
+    match (left, right) {
+        (TypeValue::Prim(Any), other) | (other, TypeValue::Prim(Any)) => Ok(other),
+        (TypeValue::Record { mut fields }, TypeValue::Record { fields: right }) => {
+            merge_fields(&mut fields, right);
+            Ok(TypeValue::Record { fields })
+        }
+        (left, right) => Err(EvalError::Intersection {
+            left: left.to_string(),
+            right: right.to_string(),
+        }),
+    }
+}
+
+impl<P> Value<P> {
+    fn into_type(self) -> Result<TypeValue<P>, EvalError> {
+        match self {
+            Value::Type(typ) => Ok(typ),
+            other => Err(EvalError::NotAType {
+                info: other.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::{parse::parse_term, Float, PrimType, ToTerm};
+
+#[cfg(test)]
+fn eval_with(input: &str, context: &str) -> Evaluated<()> {
+    let mut evaluation = Evaluation::new(());
+    let context = evaluation.eval(&parse_term(context).unwrap(), &Value::empty())?;
+    evaluation.eval(&parse_term(input).unwrap(), &context)
+}
+
+#[cfg(test)]
+fn eval_str(input: &str) -> Evaluated<()> {
+    eval_with(input, "()")
+}
+
+#[cfg(test)]
+fn record(fields: Vec<(&str, Value<()>)>) -> Value<()> {
+    let fields = fields
+        .into_iter()
+        .map(|(name, value)| (name.to_string().into(), value))
+        .collect();
+    Value::Record { fields }
+}
+
+#[cfg(test)]
+fn long(n: i64) -> Value<()> {
+    Value::Prim(Primitive::Long(n))
+}
+
+#[cfg(test)]
+fn text(s: &str) -> Value<()> {
+    Value::Prim(Primitive::Text(s.to_string()))
+}
+
+#[test]
+fn eval_primitives() {
+    assert_eq!(eval_str("38"), Ok(long(38)));
+    assert_eq!(eval_str("'Hello'"), Ok(text("Hello")));
+    assert_eq!(eval_str("()"), Ok(Value::empty()));
+}
+
+#[test]
+fn eval_record() {
+    let res = eval_str("(greet = 'Hello', 'target' = \"World\"; size = 3)");
+    assert_eq!(
+        res,
+        Ok(record(vec![
+            ("greet", text("Hello")),
+            ("target", text("World")),
+            ("size", long(3)),
+        ]))
+    );
+}
+
+#[test]
+fn eval_record_override() {
+    let res = eval_str("(a = 1, b = 2, a = 3)");
+    assert_eq!(res, Ok(record(vec![("a", long(3)), ("b", long(2))])));
+}
+
+#[test]
+fn eval_get_from_context() {
+    assert_eq!(eval_with("x", "(x = 5)"), Ok(long(5)));
+    assert_eq!(
+        eval_with("(y = x, x = 'x')", "(x = 5)"),
+        Ok(record(vec![("y", long(5)), ("x", text("x"))]))
+    );
+}
+
+#[test]
+fn eval_unbound_name() {
+    let res = eval_with("y", "(x = 5)");
+    assert_eq!(res, Err(EvalError::UnboundName("y".to_string().into())));
+}
+
+#[test]
+fn eval_then_chain() {
+    assert_eq!(eval_str("(a = 1, b = (c = 'deep')).b.c"), Ok(text("deep")));
+    assert_eq!(eval_with("r.a", "(r = (a = 1))"), Ok(long(1)));
+}
+
+#[test]
+fn eval_then_sees_only_first() {
+    let res = eval_with("(a = 1).(b = a)", "(x = 3)");
+    assert_eq!(res, Ok(record(vec![("b", long(1))])));
+    let res = eval_with("(a = 1).(b = a, c = x)", "(x = 3)");
+    assert_eq!(res, Err(EvalError::UnboundName("x".to_string().into())));
+    let res = eval_str("let a = 1 in (b = 2).a");
+    assert_eq!(res, Err(EvalError::UnboundName("a".to_string().into())));
+    // record types have no fields to look up, as in the checker
+    let res = eval_str("{a: #int}.a");
+    assert_eq!(res, Err(EvalError::UnboundName("a".to_string().into())));
+}
+
+#[test]
+fn eval_reflect() {
+    assert_eq!(eval_with("@@", "(x = 3)"), Ok(record(vec![("x", long(3))])));
+    assert_eq!(
+        eval_with("(a = 1).@@", "(x = 3)"),
+        Ok(record(vec![("a", long(1))]))
+    );
+}
+
+#[test]
+fn eval_let() {
+    let res = eval_with(
+        "let a = 1, b = (a = a, c = x) in (b = b, sum = a)",
+        "(x = 3)",
+    );
+    let b = record(vec![("a", long(1)), ("c", long(3))]);
+    assert_eq!(res, Ok(record(vec![("b", b), ("sum", long(1))])));
+    // the bindings are not a part of the context `@@` refers to
+    let res = eval_with("let a = 1 in (@@, a)", "(x = 3)");
+    let expected = Value::Record {
+        fields: vec![
+            (Key::Index(0), record(vec![("x", long(3))])),
+            (Key::Index(1), long(1)),
+        ],
+    };
+    assert_eq!(res, Ok(expected));
+    assert_eq!(
+        eval_with("let x = 'inner' in x", "(x = 3)"),
+        Ok(text("inner"))
+    );
+    let res = eval_str("(f = let a = 2 in \\{} => a).(let a = 5 in f ())");
+    assert_eq!(res, Ok(long(2)));
+}
+
+#[test]
+fn eval_imports() {
+    let term = parse_term("(a = import 'a.kers', b = import 'b.kers')").unwrap();
+    let Term::Append { left, .. } = &*term else {
+        panic!("expected a record")
+    };
+    let Term::Set { value: import, .. } = &**left else {
+        panic!("expected a field")
+    };
+    let mut imports = Imports::default();
+    imports.insert(NodeId::of(import), Value::empty(), long(1));
+    let mut evaluation = Evaluation::new(()).with_imports(Arc::new(imports));
+    let res = evaluation.eval(&term, &Value::empty());
+    assert_eq!(res, Err(EvalError::UnresolvedImport("b.kers".to_string())));
+    let res = evaluation.eval(&parse_term("import 'a.kers'").unwrap(), &Value::empty());
+    assert_eq!(res, Err(EvalError::UnresolvedImport("a.kers".to_string())));
+}
+
+#[test]
+fn eval_record_type() {
+    let res = eval_str("{name: #text, age: #int}");
+    let expected = Value::record_type(vec![
+        (
+            "name".to_string().into(),
+            TypeValue::Prim(PrimType::Text).into(),
+        ),
+        (
+            "age".to_string().into(),
+            TypeValue::Prim(PrimType::Long).into(),
+        ),
+    ]);
+    assert_eq!(res, Ok(expected));
+}
+
+#[test]
+fn eval_intersection() {
+    let res = eval_str("{name: #text} & {age: #int} & {}");
+    let expected = Value::record_type(vec![
+        (
+            "name".to_string().into(),
+            TypeValue::Prim(PrimType::Text).into(),
+        ),
+        (
+            "age".to_string().into(),
+            TypeValue::Prim(PrimType::Long).into(),
+        ),
+    ]);
+    assert_eq!(res, Ok(expected));
+
+    let res = eval_str("#int & #text");
+    assert!(matches!(res, Err(EvalError::Intersection { .. })));
+}
+
+#[test]
+fn eval_function_type() {
+    let res = eval_with("t -> #text", "(t = {x: #int})");
+    let expected = TypeValue::Function {
+        dom: Box::new(Value::record_type(vec![(
+            "x".to_string().into(),
+            TypeValue::Prim(PrimType::Long).into(),
+        )])),
+        codom: Box::new(TypeValue::Prim(PrimType::Text).into()),
+    };
+    assert_eq!(res, Ok(expected.into()));
+
+    let res = eval_str("1 -> #text");
+    assert_eq!(res, Err(EvalError::NotAType { info: "1".into() }));
+}
+
+#[cfg(test)]
+fn lambda_context(body: &str) -> Value<()> {
+    let lambda = Term::Lambda {
+        dom: parse_term("{x: #int}").unwrap(),
+        body: parse_term(body).unwrap(),
+    };
+    let mut evaluation = Evaluation::new(());
+    let context = record(vec![("y", long(10))]);
+    let func = evaluation.eval(&lambda, &context).unwrap();
+    record(vec![("f", func), ("y", long(20))])
+}
+
+#[test]
+fn eval_unlambda() {
+    let context = lambda_context("(arg = x, self = @@)");
+    let mut evaluation = Evaluation::new(());
+    let res = evaluation.eval(&parse_term("f (x = 1)").unwrap(), &context);
+    let arg = record(vec![("x", long(1))]);
+    assert_eq!(res, Ok(record(vec![("arg", long(1)), ("self", arg)])));
+
+    let res = evaluation.eval(&parse_term("(x = 2).@f").unwrap(), &context);
+    let arg = record(vec![("x", long(2))]);
+    assert_eq!(res, Ok(record(vec![("arg", long(2)), ("self", arg)])));
+}
+
+#[test]
+fn eval_lambda_closure() {
+    let context = lambda_context("y");
+    let mut evaluation = Evaluation::new(());
+    let res = evaluation.eval(&parse_term("f (x = 1)").unwrap(), &context);
+    assert_eq!(res, Ok(long(10)));
+}
+
+#[test]
+fn eval_not_a_function() {
+    let res = eval_with("(x = 1).@f", "(f = 2)");
+    assert_eq!(
+        res,
+        Err(EvalError::ValueIsNotAFunction {
+            plugin_name: CORE.to_string(),
+            info: "2".into()
+        })
+    );
+}
+
+#[test]
+fn eval_append_not_a_record() {
+    let term = Term::Append {
+        left: Term::Empty.to_arc_term(),
+        right: 2i64.to_arc_term(),
+    };
+    let res = Evaluation::new(()).eval(&term, &Value::empty());
+    assert_eq!(res, Err(EvalError::NotARecord { info: "2".into() }));
+}
+
+#[test]
+fn eval_lambda_syntax() {
+    let res = eval_with("(f = \\{x: #int} => (sum = x, y = y)).f (x = 1)", "(y = 2)");
+    assert_eq!(res, Ok(record(vec![("sum", long(1)), ("y", long(2))])));
+}
+
+#[test]
+fn eval_tuples() {
+    let res = eval_str("(1, 'two', (3,))");
+    let value = res.unwrap();
+    assert_eq!(value.to_string(), "(1, 'two', (3,))");
+    assert_eq!(eval_with("t.1", "(t = (1, 'two'))"), Ok(text("two")));
+    assert_eq!(eval_str("(1, (2, 3)).1.0"), Ok(long(2)));
+    assert_eq!(
+        eval_str("(1, 2).2"),
+        Err(EvalError::UnboundName(Key::Index(2)))
+    );
+}
+
+#[test]
+fn eval_numbers() {
+    let value = eval_str("(0, -1_000, 0b11, 2.5e-1)").unwrap();
+    assert_eq!(value.to_string(), "(0, -1000, 3, 0.25)");
+    assert_eq!(
+        eval_str("(1.0, 2.0).0"),
+        Ok(Value::Prim(Primitive::Float(Float(1.0))))
+    );
+}
+
+#[test]
+fn eval_interpolation() {
+    let context = "(target = 'World', port = 8080, ratio = 0.5)";
+    assert_eq!(
+        eval_with(r#""Hello, ${target}!""#, context),
+        Ok(text("Hello, World!"))
+    );
+    assert_eq!(
+        eval_with(r#""${target}:${port}/${ratio}""#, context),
+        Ok(text("World:8080/0.5"))
+    );
+    assert_eq!(
+        eval_with(r#""${(x = 1)}""#, context),
+        Err(EvalError::NotText {
+            info: "(x = 1)".to_string()
+        })
+    );
 }
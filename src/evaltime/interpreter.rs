@@ -6,7 +6,7 @@ use crate::fp::{Compose, Prism, ToLeft, ToRight};
 
 use super::{ruintime::Runtime, values::Value};
 
-pub trait Interpteter: Clone{
+pub trait Interpteter: Clone {
     type Val;
     type Plug<'a, V: 'a, P>: Plugin<Val = V, Own = Self::Val> + 'a;
     fn plug_in<'a, V, P: Prism<Super = V, Sub = Self::Val>>(
@@ -20,6 +20,7 @@ pub trait Plugin: Sized {
     type Val;
     type Own;
     fn roots(&mut self) -> Vec<Value<Self::Val>>;
+    #[allow(clippy::result_unit_err)]
     fn then(&mut self, context: Value<Self::Val>, term: Self::Own) -> Result<Value<Self::Val>, ()>;
 }
 
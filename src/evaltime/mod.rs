@@ -1,7 +1,7 @@
 pub mod values;
-mod checking;
+pub mod checking;
 mod variables;
 mod renaming;
-mod evaluate;
-mod interpreter;
-mod ruintime;
\ No newline at end of file
+pub mod evaluate;
+pub mod interpreter;
+mod ruintime;
@@ -1,34 +1,301 @@
+use std::fmt;
+use std::sync::Arc;
+
 use crate::evaltime::interpreter::Interpteter;
-use crate::{PrimType, Primitive};
+use crate::{Key, PrimType, Primitive, Term};
 
 use super::variables::VarIdx;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum TypeValue<P> {
     Prim(PrimType),
     Function {
-        dom: Box<TypeValue<P>>,
-        codom: Box<TypeValue<P>>,
+        dom: Box<Value<P>>,
+        codom: Box<Value<P>>,
     },
     Record {
-        fields: Vec<Value<P>>,
+        fields: Vec<(Key, Value<P>)>,
     },
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Value<P> {
     Prim(Primitive),
     Type(TypeValue<P>),
     Variable(VarIdx),
     Record {
-        fields: Vec<Value<P>>,
+        fields: Vec<(Key, Value<P>)>,
     },
     Lambda {
         dom: Box<Value<P>>,
-        term: Box<Value<P>>,
+        body: Arc<Term>,
+        scope: Scope<P>,
     },
     External(P),
 }
 
+/// Chain of contexts a term is evaluated in.
+/// The innermost frame is the value `@@` refers to,
+/// names are looked up starting from it and going outwards.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scope<P>(Option<Arc<Frame<P>>>);
+
+#[derive(Debug, PartialEq)]
+struct Frame<P> {
+    value: Value<P>,
+    kind: FrameKind,
+    /// Values of the `let` bindings whose types are in `value`, known while type-checking
+    defined: Option<Value<P>>,
+    parent: Scope<P>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    /// Context of a term, names missing from it are looked up in the outer frames
+    Context,
+    /// Record the `.` continues with, names are looked up only in it
+    Chained,
+    /// `let` bindings, their names are visible but they are not the value of `@@`
+    Local,
+}
+
+impl<P> Default for Scope<P> {
+    fn default() -> Self {
+        Scope(None)
+    }
+}
+
+impl<P: Clone> Scope<P> {
+    pub fn root(value: Value<P>) -> Self {
+        Scope::default().push(value)
+    }
+
+    pub fn push(&self, value: Value<P>) -> Self {
+        self.frame(value, FrameKind::Context)
+    }
+
+    /// Context of the term after `.`, which sees only the names of the record
+    pub fn chain(&self, value: Value<P>) -> Self {
+        self.frame(value, FrameKind::Chained)
+    }
+
+    /// Adds the fields of the record to the names in scope, leaving the current context as it is
+    pub fn bind(&self, value: Value<P>) -> Self {
+        self.frame(value, FrameKind::Local)
+    }
+
+    /// Binds the names to their types, along with the values they are bound to
+    pub fn define(&self, types: Value<P>, values: Value<P>) -> Self {
+        self.with_frame(types, FrameKind::Local, Some(values))
+    }
+
+    fn frame(&self, value: Value<P>, kind: FrameKind) -> Self {
+        self.with_frame(value, kind, None)
+    }
+
+    fn with_frame(&self, value: Value<P>, kind: FrameKind, defined: Option<Value<P>>) -> Self {
+        Scope(Some(Arc::new(Frame {
+            value,
+            kind,
+            defined,
+            parent: self.clone(),
+        })))
+    }
+
+    fn context(&self) -> Option<&Frame<P>> {
+        let mut scope = self;
+        while let Some(frame) = &scope.0 {
+            if frame.kind != FrameKind::Local {
+                return Some(frame);
+            }
+            scope = &frame.parent;
+        }
+        None
+    }
+
+    pub fn current(&self) -> Option<&Value<P>> {
+        self.context().map(|frame| &frame.value)
+    }
+
+    /// Scope surrounding the current context, the functions applied with `@` are looked up in it
+    pub fn outer(&self) -> Self {
+        self.context()
+            .map(|frame| frame.parent.clone())
+            .unwrap_or_default()
+    }
+
+    /// Scope of the defined values visible from this one, the names whose values are unknown are unbound
+    pub fn definitions(&self) -> Self {
+        let mut shadowed = vec![];
+        let mut defined = vec![];
+        let mut scope = self;
+        while let Some(frame) = &scope.0 {
+            match (&frame.defined, &frame.value) {
+                (Some(Value::Record { fields }), _) => {
+                    let visible = fields.iter().filter(|(name, _)| !shadowed.contains(&name));
+                    defined.extend(visible.cloned());
+                }
+                (_, Value::Record { fields } | Value::Type(TypeValue::Record { fields })) => {
+                    shadowed.extend(fields.iter().map(|(name, _)| name));
+                }
+                _ => {}
+            }
+            if frame.kind == FrameKind::Chained {
+                break;
+            }
+            scope = &frame.parent;
+        }
+        let root = Scope::root(Value::empty());
+        defined.into_iter().rev().fold(root, |scope, field| {
+            scope.bind(Value::Record {
+                fields: vec![field],
+            })
+        })
+    }
+
+    pub fn lookup(&self, key: &Key) -> Option<&Value<P>> {
+        self.find(key, Value::field)
+    }
+
+    /// Type of the name, the frames are record types while type-checking
+    pub fn lookup_type(&self, key: &Key) -> Option<&Value<P>> {
+        self.find(key, Value::field_type)
+    }
+
+    fn find<'a>(
+        &'a self,
+        key: &Key,
+        field: impl Fn(&'a Value<P>, &Key) -> Option<&'a Value<P>>,
+    ) -> Option<&'a Value<P>> {
+        let mut scope = self;
+        while let Some(frame) = &scope.0 {
+            if let Some(value) = field(&frame.value, key) {
+                return Some(value);
+            }
+            if frame.kind == FrameKind::Chained {
+                return None;
+            }
+            scope = &frame.parent;
+        }
+        None
+    }
+}
+
+impl<P> Value<P> {
+    pub fn empty() -> Self {
+        Value::Record { fields: vec![] }
+    }
+
+    pub fn record_type(fields: Vec<(Key, Value<P>)>) -> Self {
+        Value::Type(TypeValue::Record { fields })
+    }
+
+    /// Field of a record
+    pub fn field(&self, key: &Key) -> Option<&Value<P>> {
+        match self {
+            Value::Record { fields } => fields.iter().find(|(name, _)| name == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Type of the field of a record type
+    pub fn field_type(&self, key: &Key) -> Option<&Value<P>> {
+        match self {
+            Value::Type(TypeValue::Record { fields }) => {
+                fields.iter().find(|(name, _)| name == key).map(|(_, v)| v)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<P> From<TypeValue<P>> for Value<P> {
+    fn from(typ: TypeValue<P>) -> Self {
+        Value::Type(typ)
+    }
+}
+
+/// Appends fields of `right` to `left`, fields of `right` replace fields of `left` with the same key
+pub fn merge_fields<P>(left: &mut Vec<(Key, Value<P>)>, right: Vec<(Key, Value<P>)>) {
+    for (key, value) in right {
+        match left.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, old)) => *old = value,
+            None => left.push((key, value)),
+        }
+    }
+}
+
 #[allow(unused)]
 impl<P: Interpteter> Value<P> {}
+
+impl<P> fmt::Display for Value<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Prim(prim) => write!(f, "{prim}"),
+            Value::Type(typ) => write!(f, "{typ}"),
+            Value::Variable(idx) => write!(f, "?{idx:?}"),
+            Value::Record { fields } if fields.is_empty() => f.write_str("()"),
+            Value::Record { fields } if is_positional(fields) => write_elems(f, ("(", ")"), fields),
+            Value::Record { fields } => write_fields(f, ("(", " = ", ")"), fields),
+            Value::Lambda { dom, .. } => write!(f, "<lambda {dom}>"),
+            Value::External(_) => f.write_str("<external>"),
+        }
+    }
+}
+
+impl<P> fmt::Display for TypeValue<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeValue::Prim(PrimType::Text) => f.write_str("#text"),
+            TypeValue::Prim(PrimType::Long) => f.write_str("#int"),
+            TypeValue::Prim(PrimType::Float) => f.write_str("#float"),
+            TypeValue::Prim(PrimType::Universe) => f.write_str("*"),
+            TypeValue::Prim(PrimType::Any) => f.write_str("{}"),
+            TypeValue::Function { dom, codom } => write!(f, "({dom} -> {codom})"),
+            TypeValue::Record { fields } if fields.is_empty() => f.write_str("{}"),
+            TypeValue::Record { fields } if is_positional(fields) => {
+                write_elems(f, ("{", "}"), fields)
+            }
+            TypeValue::Record { fields } => write_fields(f, ("{", ": ", "}"), fields),
+        }
+    }
+}
+
+fn write_fields<P>(
+    f: &mut fmt::Formatter<'_>,
+    (open, sep, close): (&str, &str, &str),
+    fields: &[(Key, Value<P>)],
+) -> fmt::Result {
+    f.write_str(open)?;
+    for (i, (key, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{key}{sep}{value}")?;
+    }
+    f.write_str(close)
+}
+
+/// Whether fields are keyed by consecutive indices starting from zero, as in a tuple
+pub(crate) fn is_positional<P>(fields: &[(Key, Value<P>)]) -> bool {
+    let indexed = |(idx, (key, _)): (usize, &(Key, Value<P>))| *key == Key::Index(idx);
+    fields.iter().enumerate().all(indexed)
+}
+
+fn write_elems<P>(
+    f: &mut fmt::Formatter<'_>,
+    (open, close): (&str, &str),
+    fields: &[(Key, Value<P>)],
+) -> fmt::Result {
+    f.write_str(open)?;
+    for (i, (_, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{value}")?;
+    }
+    if fields.len() == 1 {
+        f.write_str(",")?;
+    }
+    f.write_str(close)
+}
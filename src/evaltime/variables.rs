@@ -1,6 +1,8 @@
 use std::marker::PhantomData;
 
-use slotmap::new_key_type;
+use slotmap::{new_key_type, SlotMap};
+
+use super::values::{TypeValue, Value};
 
 new_key_type! {
     pub  struct VarIdx;
@@ -8,3 +10,178 @@ new_key_type! {
 
 #[allow(unused)]
 pub struct Var<'a>(VarIdx, PhantomData<&'a ()>);
+
+/// Store of metavariables, holes in types that are solved during checking
+#[derive(Debug)]
+pub struct Variables<P> {
+    vars: SlotMap<VarIdx, Option<Value<P>>>,
+}
+
+impl<P> Default for Variables<P> {
+    fn default() -> Self {
+        Variables {
+            vars: SlotMap::with_key(),
+        }
+    }
+}
+
+impl<P: Clone> Variables<P> {
+    pub fn fresh(&mut self) -> VarIdx {
+        self.vars.insert(None)
+    }
+
+    pub fn solution(&self, idx: VarIdx) -> Option<&Value<P>> {
+        self.vars.get(idx).and_then(Option::as_ref)
+    }
+
+    /// Follows solved variables until an unsolved variable or a non-variable value,
+    /// every solved variable on the way is pointed directly to the result
+    pub fn resolve(&mut self, value: Value<P>) -> Value<P> {
+        let Value::Variable(idx) = value else {
+            return value;
+        };
+        let Some(solution) = self.solution(idx).cloned() else {
+            return value;
+        };
+        let resolved = self.resolve(solution);
+        self.vars[idx] = Some(resolved.clone());
+        resolved
+    }
+
+    /// Solves unsolved variable `idx` with `value`,
+    /// returns the fully resolved value when `idx` occurs in it.
+    /// Solved variables are never solved again, [`Variables::resolve`] gives their solutions.
+    pub fn solve(&mut self, idx: VarIdx, value: Value<P>) -> Result<(), Value<P>> {
+        debug_assert!(self.vars[idx].is_none(), "variable {idx:?} is already solved");
+        let value = self.zonk(value);
+        if occurs(idx, &value) {
+            return Err(value);
+        }
+        self.vars[idx] = Some(value);
+        Ok(())
+    }
+
+    /// Substitutes all solved variables inside of the `value`
+    pub fn zonk(&mut self, value: Value<P>) -> Value<P> {
+        match self.resolve(value) {
+            Value::Type(typ) => Value::Type(self.zonk_type(typ)),
+            Value::Record { fields } => Value::Record {
+                fields: self.zonk_fields(fields),
+            },
+            Value::Lambda { dom, body, scope } => Value::Lambda {
+                dom: Box::new(self.zonk(*dom)),
+                body,
+                scope,
+            },
+            other => other,
+        }
+    }
+
+    fn zonk_type(&mut self, typ: TypeValue<P>) -> TypeValue<P> {
+        match typ {
+            TypeValue::Prim(prim) => TypeValue::Prim(prim),
+            TypeValue::Function { dom, codom } => TypeValue::Function {
+                dom: Box::new(self.zonk(*dom)),
+                codom: Box::new(self.zonk(*codom)),
+            },
+            TypeValue::Record { fields } => TypeValue::Record {
+                fields: self.zonk_fields(fields),
+            },
+        }
+    }
+
+    fn zonk_fields<K>(&mut self, fields: Vec<(K, Value<P>)>) -> Vec<(K, Value<P>)> {
+        fields
+            .into_iter()
+            .map(|(key, value)| (key, self.zonk(value)))
+            .collect()
+    }
+}
+
+fn occurs<P>(idx: VarIdx, value: &Value<P>) -> bool {
+    let in_fields = |fields: &[(_, Value<P>)]| fields.iter().any(|(_, v)| occurs(idx, v));
+    match value {
+        Value::Variable(other) => *other == idx,
+        Value::Record { fields } | Value::Type(TypeValue::Record { fields }) => in_fields(fields),
+        Value::Type(TypeValue::Function { dom, codom }) => occurs(idx, dom) || occurs(idx, codom),
+        Value::Lambda { dom, .. } => occurs(idx, dom),
+        Value::Type(TypeValue::Prim(_)) | Value::Prim(_) | Value::External(_) => false,
+    }
+}
+
+#[cfg(test)]
+use crate::PrimType;
+
+#[cfg(test)]
+fn int() -> Value<()> {
+    TypeValue::Prim(PrimType::Long).into()
+}
+
+#[test]
+fn fresh_variables_are_unsolved() {
+    let mut vars = Variables::<()>::default();
+    let (a, b) = (vars.fresh(), vars.fresh());
+    assert_ne!(a, b);
+    assert_eq!(vars.solution(a), None);
+    assert_eq!(vars.resolve(Value::Variable(a)), Value::Variable(a));
+}
+
+#[test]
+fn resolve_compresses_paths() {
+    let mut vars = Variables::<()>::default();
+    let (a, b, c) = (vars.fresh(), vars.fresh(), vars.fresh());
+    vars.solve(a, Value::Variable(b)).unwrap();
+    vars.solve(b, Value::Variable(c)).unwrap();
+    vars.solve(c, int()).unwrap();
+
+    assert_eq!(vars.solution(a), Some(&Value::Variable(b)));
+    assert_eq!(vars.resolve(Value::Variable(a)), int());
+    assert_eq!(vars.solution(a), Some(&int()));
+    assert_eq!(vars.solution(b), Some(&int()));
+}
+
+#[test]
+fn zonk_substitutes_nested_variables() {
+    let mut vars = Variables::<()>::default();
+    let (a, b) = (vars.fresh(), vars.fresh());
+    vars.solve(a, int()).unwrap();
+    let function = TypeValue::Function {
+        dom: Box::new(Value::record_type(vec![(
+            "x".to_string().into(),
+            Value::Variable(a),
+        )])),
+        codom: Box::new(Value::Variable(b)),
+    };
+    let expected = TypeValue::Function {
+        dom: Box::new(Value::record_type(vec![("x".to_string().into(), int())])),
+        codom: Box::new(Value::Variable(b)),
+    };
+    assert_eq!(vars.zonk(function.into()), expected.into());
+}
+
+#[test]
+fn solve_performs_occurs_check() {
+    let mut vars = Variables::<()>::default();
+    let (a, b) = (vars.fresh(), vars.fresh());
+    vars.solve(b, Value::Variable(a)).unwrap();
+    let looped = Value::record_type(vec![("x".to_string().into(), Value::Variable(b))]);
+    let res = vars.solve(a, looped);
+    assert_eq!(
+        res,
+        Err(Value::record_type(vec![(
+            "x".to_string().into(),
+            Value::Variable(a)
+        )]))
+    );
+    assert_eq!(vars.solution(a), None);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "is already solved")]
+fn solve_rejects_solved_variables() {
+    let mut vars = Variables::<()>::default();
+    let a = vars.fresh();
+    vars.solve(a, int()).unwrap();
+    let _ = vars.solve(a, Value::empty());
+}
@@ -0,0 +1,343 @@
+use std::fmt;
+
+use serde_json::{Number, Value as Json};
+use serde_yaml::Value as Yaml;
+use thiserror::Error;
+use toml::Value as Toml;
+
+use crate::{
+    evaltime::values::{is_positional, Value},
+    Key, Primitive,
+};
+
+/// Location of a value inside of the exported one, as the chain of fields leading to it
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Path(pub Vec<Key>);
+
+impl Path {
+    pub(crate) fn with(&self, key: &Key) -> Path {
+        let mut keys = self.0.clone();
+        keys.push(key.clone());
+        Path(keys)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str(".");
+        }
+        for key in &self.0 {
+            write!(f, ".{key}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ExportError {
+    #[error("at {path}: {what} has no {format} representation")]
+    Unsupported {
+        path: Path,
+        what: String,
+        format: &'static str,
+    },
+}
+
+/// Text formats evaluated values can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+        }
+    }
+}
+
+/// Renders the evaluated value in the `format`
+///
+/// Records with names become objects and records keyed by consecutive indices starting from zero become arrays,
+/// numbers and texts become numbers and strings of the format.
+/// Functions, types and plugin values can not be exported.
+pub fn export<P>(value: &Value<P>, format: Format) -> Result<String, ExportError> {
+    match format {
+        Format::Json => to_json(value),
+        Format::Yaml => to_yaml(value),
+        Format::Toml => to_toml(value),
+    }
+}
+
+/// Renders the evaluated value as a pretty-printed JSON document, the mapping is described in [`export`]
+pub fn to_json<P>(value: &Value<P>) -> Result<String, ExportError> {
+    let json = to_json_value(value)?;
+    Ok(serde_json::to_string_pretty(&json).expect("JSON values are always serializable"))
+}
+
+/// Converts the evaluated value into a JSON tree, the mapping is described in [`export`]
+pub fn to_json_value<P>(value: &Value<P>) -> Result<Json, ExportError> {
+    convert(value, &Path::default())
+}
+
+/// Renders the evaluated value as a YAML document, the mapping is described in [`export`]
+///
+/// Multi-line texts are written as literal block scalars.
+pub fn to_yaml<P>(value: &Value<P>) -> Result<String, ExportError> {
+    let yaml: Yaml = convert(value, &Path::default())?;
+    Ok(serde_yaml::to_string(&yaml).expect("YAML values are always serializable"))
+}
+
+/// Renders the evaluated value as a TOML document, the mapping is described in [`export`]
+///
+/// The value has to be a record with names, it becomes the top-level table.
+/// Nested records become tables and arrays of records become arrays of tables,
+/// multi-line texts are written as multi-line strings.
+pub fn to_toml<P>(value: &Value<P>) -> Result<String, ExportError> {
+    let toml: Toml = convert(value, &Path::default())?;
+    match toml {
+        Toml::Table(table) => {
+            Ok(toml::to_string_pretty(&table).expect("TOML tables are always serializable"))
+        }
+        _ => Err(ExportError::Unsupported {
+            path: Path::default(),
+            what: format!("the top level value {value}"),
+            format: Format::Toml.name(),
+        }),
+    }
+}
+
+/// Tree of values of a text format
+trait Document: Sized {
+    const FORMAT: Format;
+    fn prim(prim: &Primitive) -> Option<Self>;
+    fn array(elems: Vec<Self>) -> Self;
+    fn object(fields: Vec<(String, Self)>) -> Self;
+}
+
+impl Document for Json {
+    const FORMAT: Format = Format::Json;
+
+    fn prim(prim: &Primitive) -> Option<Self> {
+        match prim {
+            Primitive::Long(n) => Some(Json::from(*n)),
+            Primitive::Float(x) => Number::from_f64(x.0).map(Json::Number),
+            Primitive::Text(text) => Some(Json::String(text.clone())),
+        }
+    }
+
+    fn array(elems: Vec<Self>) -> Self {
+        Json::Array(elems)
+    }
+
+    fn object(fields: Vec<(String, Self)>) -> Self {
+        Json::Object(fields.into_iter().collect())
+    }
+}
+
+impl Document for Yaml {
+    const FORMAT: Format = Format::Yaml;
+
+    fn prim(prim: &Primitive) -> Option<Self> {
+        match prim {
+            Primitive::Long(n) => Some(Yaml::from(*n)),
+            Primitive::Float(x) => Some(Yaml::from(x.0)),
+            Primitive::Text(text) => Some(Yaml::String(text.clone())),
+        }
+    }
+
+    fn array(elems: Vec<Self>) -> Self {
+        Yaml::Sequence(elems)
+    }
+
+    fn object(fields: Vec<(String, Self)>) -> Self {
+        let mapping = fields
+            .into_iter()
+            .map(|(name, value)| (Yaml::String(name), value));
+        Yaml::Mapping(mapping.collect())
+    }
+}
+
+impl Document for Toml {
+    const FORMAT: Format = Format::Toml;
+
+    fn prim(prim: &Primitive) -> Option<Self> {
+        match prim {
+            Primitive::Long(n) => Some(Toml::Integer(*n)),
+            Primitive::Float(x) => Some(Toml::Float(x.0)),
+            Primitive::Text(text) => Some(Toml::String(text.clone())),
+        }
+    }
+
+    fn array(elems: Vec<Self>) -> Self {
+        Toml::Array(elems)
+    }
+
+    fn object(fields: Vec<(String, Self)>) -> Self {
+        Toml::Table(fields.into_iter().collect())
+    }
+}
+
+fn convert<D: Document, P>(value: &Value<P>, path: &Path) -> Result<D, ExportError> {
+    let unsupported = |what: String| ExportError::Unsupported {
+        path: path.clone(),
+        what,
+        format: D::FORMAT.name(),
+    };
+    match value {
+        Value::Prim(prim) => D::prim(prim).ok_or_else(|| unsupported(format!("the number {prim}"))),
+        Value::Record { fields } if !fields.is_empty() && is_positional(fields) => fields
+            .iter()
+            .map(|(key, value)| convert(value, &path.with(key)))
+            .collect::<Result<_, _>>()
+            .map(D::array),
+        Value::Record { fields } => fields
+            .iter()
+            .map(|(key, value)| Ok((key_name(key), convert(value, &path.with(key))?)))
+            .collect::<Result<_, _>>()
+            .map(D::object),
+        Value::Lambda { .. } => Err(unsupported("a function".to_string())),
+        Value::Type(_) => Err(unsupported(format!("the type {value}"))),
+        Value::Variable(_) => Err(unsupported("an unknown type".to_string())),
+        Value::External(_) => Err(unsupported("a plugin value".to_string())),
+    }
+}
+
+/// Name of the field in the exported object, indices are written as decimal numbers
+pub(crate) fn key_name(key: &Key) -> String {
+    match key {
+        Key::Name(name) => name.clone(),
+        Key::Index(idx) => idx.to_string(),
+    }
+}
+
+#[cfg(test)]
+use crate::{evaltime::evaluate::Evaluation, parse::parse_term, Float};
+
+#[cfg(test)]
+fn export_str(input: &str) -> Result<String, ExportError> {
+    let value = Evaluation::new(())
+        .eval(&parse_term(input).unwrap(), &Value::empty())
+        .unwrap();
+    to_json(&value)
+}
+
+#[test]
+fn export_json() {
+    let exported = export_str("(name = 'kers', version = (0, 1), ratio = 0.5, empty = ())");
+    let expected = r#"{
+  "name": "kers",
+  "version": [
+    0,
+    1
+  ],
+  "ratio": 0.5,
+  "empty": {}
+}"#;
+    assert_eq!(exported.unwrap(), expected);
+    assert_eq!(export_str("-42").unwrap(), "-42");
+    assert_eq!(export_str("'quote \" \\n'").unwrap(), r#""quote \" \n""#);
+    assert_eq!(
+        export_str("('1' = 'a', 'x y' = 'b')").unwrap(),
+        "{\n  \"1\": \"a\",\n  \"x y\": \"b\"\n}"
+    );
+}
+
+#[test]
+fn export_json_errors() {
+    let err = export_str("(server = (handler = \\{} => 1))").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .server.handler: a function has no JSON representation"
+    );
+    let err = export_str("(types = (#int, {a: #text}))").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .types.0: the type #int has no JSON representation"
+    );
+    let err = to_json(&Value::<()>::Prim(Primitive::Float(Float(f64::NAN)))).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .: the number NaN has no JSON representation"
+    );
+}
+
+#[cfg(test)]
+fn export_as(input: &str, format: Format) -> Result<String, ExportError> {
+    let value = Evaluation::new(())
+        .eval(&parse_term(input).unwrap(), &Value::empty())
+        .unwrap();
+    export(&value, format)
+}
+
+#[test]
+fn export_yaml() {
+    let exported = export_as(
+        "(name = 'kers', script = 'one\\ntwo\\n', ports = (80, 443), empty = (), 'x: y' = (a = 1.5, '0' = 'zero'))",
+        Format::Yaml,
+    );
+    let expected = "\
+name: kers
+script: |
+  one
+  two
+ports:
+- 80
+- 443
+empty: {}
+'x: y':
+  a: 1.5
+  '0': zero
+";
+    assert_eq!(exported.unwrap(), expected);
+    let err = export_as("(a = (\\{} => 1, 2))", Format::Yaml).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .a.0: a function has no YAML representation"
+    );
+}
+
+#[test]
+fn export_toml() {
+    let exported = export_as(
+        "(name = 'kers', servers = ((host = 'a', port = 1), (host = 'b', port = 2)), db = (url = 'x'), script = 'one\\ntwo', mixed = (1, 'a', (b = 2)))",
+        Format::Toml,
+    );
+    let expected = "\
+name = \"kers\"
+script = \"\"\"
+one
+two\"\"\"
+mixed = [
+    1,
+    \"a\",
+    { b = 2 },
+]
+
+[[servers]]
+host = \"a\"
+port = 1
+
+[[servers]]
+host = \"b\"
+port = 2
+
+[db]
+url = \"x\"
+";
+    assert_eq!(exported.unwrap(), expected);
+    let err = export_as("(1, 2)", Format::Toml).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .: the top level value (1, 2) has no TOML representation"
+    );
+    let err = export_as("'text'", Format::Toml).unwrap_err();
+    assert!(err.to_string().contains("has no TOML representation"));
+    assert!(export_as("(nested = ((), (a = ())), e = ())", Format::Toml).is_ok());
+}
@@ -0,0 +1,660 @@
+use pest::{iterators::Pair, Parser};
+
+use crate::{
+    language::source::Sources,
+    parse::{Kers, ParseError, Rule, SyntaxError},
+};
+
+/// Layout settings of the formatter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Preferred maximum line width
+    pub width: usize,
+    /// Spaces added for every nesting level
+    pub indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            width: 80,
+            indent: 2,
+        }
+    }
+}
+
+/// Rewrites the kers source in the canonical layout with the default options
+pub fn format_str(input: &str) -> Result<String, ParseError> {
+    format_named("<string>", input, &FormatOptions::default())
+}
+
+/// Rewrites the kers source in the canonical layout keeping all the comments, `name` is used in diagnostics
+pub fn format_named(
+    name: impl Into<String>,
+    input: &str,
+    options: &FormatOptions,
+) -> Result<String, ParseError> {
+    let name = name.into();
+    let mut sources = Sources::default();
+    let file = sources.add(name.clone(), input);
+    let mut top = match Kers::parse(Rule::file, input) {
+        Ok(top) => top,
+        Err(err) => {
+            return Err(ParseError::Syntax {
+                name,
+                error: SyntaxError::parse_error(err, file),
+                sources,
+            })
+        }
+    };
+    let Some(pair) = top.next() else {
+        unreachable!("pest returns the parsed rule on success")
+    };
+    let doc = Layout { input }.file(pair);
+    Ok(render(&doc, options))
+}
+
+/// Document to be laid out, groups are printed on a single line when they fit
+#[derive(Debug, Clone)]
+enum Doc {
+    Text(String),
+    /// A space, or a line break when the enclosing group does not fit
+    Line,
+    /// Nothing, or a line break when the enclosing group does not fit
+    SoftLine,
+    /// Always a line break, the enclosing groups never fit
+    HardLine,
+    /// An empty line kept from the source when the enclosing group does not fit
+    EmptyLine,
+    /// Text printed only when the enclosing group does not fit
+    IfBreak(&'static str),
+    /// Makes the enclosing groups break without printing anything
+    BreakParent,
+    Nest(Box<Doc>),
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+fn text(s: impl Into<String>) -> Doc {
+    Doc::Text(s.into())
+}
+
+fn nest(doc: Doc) -> Doc {
+    Doc::Nest(Box::new(doc))
+}
+
+fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+/// Comment found between the syntax nodes
+struct Comment<'a> {
+    text: &'a str,
+    /// `//` comment, the line has to end after it
+    line: bool,
+    /// There is a line break between the preceding token and the comment
+    own_line: bool,
+    /// There is an empty line between the preceding token and the comment
+    empty_before: bool,
+}
+
+/// Comments in the source between two nodes
+#[derive(Default)]
+struct Gap<'a> {
+    comments: Vec<Comment<'a>>,
+    /// Whether an empty line separates the following node from the preceding comments or node
+    empty_line: bool,
+    /// There are no tokens besides the comments
+    trivia: bool,
+}
+
+impl<'a> Gap<'a> {
+    /// Collects comments from the text between nodes, which also contains punctuation
+    fn scan(text: &'a str) -> Self {
+        let mut gap = Gap {
+            trivia: true,
+            ..Gap::default()
+        };
+        let mut newlines = 0;
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            let len = if rest.starts_with("//") {
+                rest.find('\n').unwrap_or(rest.len())
+            } else if rest.starts_with("/*") {
+                block_comment_len(rest)
+            } else {
+                if c == '\n' {
+                    newlines += 1;
+                }
+                gap.trivia &= c.is_whitespace();
+                rest = &rest[c.len_utf8()..];
+                continue;
+            };
+            gap.comments.push(Comment {
+                text: rest[..len].trim_end(),
+                line: rest.starts_with("//"),
+                own_line: newlines > 0,
+                empty_before: newlines > 1,
+            });
+            newlines = 0;
+            rest = &rest[len..];
+        }
+        gap.empty_line = newlines > 1;
+        gap
+    }
+
+    /// Splits off comments on the same line as the preceding node
+    fn trailing(&mut self) -> Vec<Comment<'a>> {
+        let same_line = self.comments.iter().take_while(|c| !c.own_line).count();
+        self.comments.drain(..same_line).collect()
+    }
+
+    /// Forgets the empty lines, there should be none right after an opening bracket
+    fn drop_empty_lines(&mut self) {
+        self.empty_line = false;
+        if let Some(first) = self.comments.first_mut() {
+            first.empty_before = false;
+        }
+    }
+
+    /// Comments kept in place between tokens
+    fn inline(self) -> Doc {
+        let mut docs = vec![];
+        for comment in self.comments {
+            docs.push(text(comment.text));
+            docs.push(if comment.line {
+                Doc::HardLine
+            } else {
+                text(" ")
+            });
+        }
+        Doc::Concat(docs)
+    }
+
+    /// Comments on their own lines before a node, keeping the empty lines between them
+    fn leading(self) -> Doc {
+        let mut docs = vec![];
+        for comment in self.comments {
+            if comment.empty_before {
+                docs.push(Doc::EmptyLine);
+            }
+            docs.push(text(comment.text));
+            docs.push(if comment.line || comment.own_line {
+                Doc::HardLine
+            } else {
+                text(" ")
+            });
+        }
+        Doc::Concat(docs)
+    }
+}
+
+fn trailing(comments: Vec<Comment>) -> Doc {
+    let mut docs = vec![];
+    for comment in comments {
+        docs.push(text(" "));
+        docs.push(text(comment.text));
+        if comment.line {
+            docs.push(Doc::BreakParent);
+        }
+    }
+    Doc::Concat(docs)
+}
+
+/// Length of the possibly nested block comment at the start of the text
+fn block_comment_len(text: &str) -> usize {
+    let mut depth = 0;
+    let mut idx = 0;
+    while idx < text.len() {
+        if text[idx..].starts_with("/*") {
+            depth += 1;
+            idx += 2;
+        } else if text[idx..].starts_with("*/") {
+            depth -= 1;
+            idx += 2;
+            if depth == 0 {
+                return idx;
+            }
+        } else {
+            idx += text[idx..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    text.len()
+}
+
+/// Builds the document from the parse tree, finding comments in the source between the nodes
+struct Layout<'a> {
+    input: &'a str,
+}
+
+impl<'a> Layout<'a> {
+    /// Children of the node with the comments before each of them, and the comments after the last one
+    fn children(&self, pair: Pair<'a, Rule>) -> (Vec<(Gap<'a>, Pair<'a, Rule>)>, Gap<'a>) {
+        let mut pos = pair.as_span().start();
+        let end = self.end(&pair);
+        let mut children = vec![];
+        for child in pair.into_inner() {
+            if child.as_rule() == Rule::EOI {
+                continue;
+            }
+            let gap = Gap::scan(&self.input[pos..child.as_span().start()]);
+            pos = self.end(&child);
+            children.push((gap, child));
+        }
+        (children, Gap::scan(&self.input[pos..end]))
+    }
+
+    /// End of the last token of the node, pest spans of repetitions include the whitespace and comments after them
+    fn end(&self, pair: &Pair<'a, Rule>) -> usize {
+        let end = pair.as_span().end();
+        match pair.clone().into_inner().last() {
+            Some(last) => {
+                let inner = self.end(&last);
+                if Gap::scan(&self.input[inner..end]).trivia {
+                    inner
+                } else {
+                    end
+                }
+            }
+            None => end,
+        }
+    }
+
+    fn file(&self, file: Pair<'a, Rule>) -> Doc {
+        let (children, mut after) = self.children(file);
+        let mut docs = vec![];
+        for (before, term) in children {
+            docs.push(before.leading());
+            docs.push(self.node(term));
+        }
+        docs.push(trailing(after.trailing()));
+        for comment in after.comments {
+            docs.push(Doc::HardLine);
+            docs.push(text(comment.text));
+        }
+        docs.push(Doc::HardLine);
+        Doc::Concat(docs)
+    }
+
+    fn node(&self, pair: Pair<'a, Rule>) -> Doc {
+        match pair.as_rule() {
+            Rule::term => self.joined(pair, |_| vec![]),
+            Rule::atomic_term => {
+                let parenthesized = pair.clone().into_inner().any(|p| p.as_rule() == Rule::term);
+                let inner = self.joined(pair, |_| vec![]);
+                if parenthesized {
+                    Doc::Concat(vec![text("("), inner, text(")")])
+                } else {
+                    inner
+                }
+            }
+            Rule::let_term => self.binding(pair),
+            Rule::lambda => self.joined(pair, |idx| match idx {
+                0 => vec![text("\\")],
+                _ => vec![text(" => ")],
+            }),
+            Rule::lam_sequence => self.chain(pair, " ->"),
+            Rule::intersection => self.chain(pair, " &"),
+            Rule::application => self.chain(pair, ""),
+            Rule::then_chain => self.joined(pair, |idx| match idx {
+                0 => vec![],
+                _ => vec![text(".")],
+            }),
+            Rule::modified_term => {
+                // `@@` after a modifier would be read as reflection
+                let modifiers = pair
+                    .clone()
+                    .into_inner()
+                    .filter(|p| p.as_rule() == Rule::modifier)
+                    .count();
+                let reflected = pair.as_str().ends_with("@@");
+                self.joined(pair, |idx| match idx {
+                    0 => vec![],
+                    idx if idx < modifiers || reflected => vec![text(" ")],
+                    _ => vec![],
+                })
+            }
+            Rule::modifier => text("@"),
+            Rule::import => self.joined(pair, |idx| match idx {
+                0 => vec![text("import ")],
+                _ => vec![text(" ")],
+            }),
+            Rule::assignment | Rule::ascription => self.entry(pair),
+            Rule::record | Rule::tuple => self.sequence(pair, "(", ")"),
+            Rule::record_type | Rule::tuple_type => self.sequence(pair, "{", "}"),
+            Rule::unit_type => text("{}"),
+            _ => text(pair.as_str()),
+        }
+    }
+
+    /// Children one after another, `before` gives the punctuation preceding the child with the index
+    fn joined(&self, pair: Pair<'a, Rule>, before: impl Fn(usize) -> Vec<Doc>) -> Doc {
+        let (children, after) = self.children(pair);
+        let mut docs = vec![];
+        for (idx, (gap, child)) in children.into_iter().enumerate() {
+            docs.extend(before(idx));
+            docs.push(gap.inline());
+            docs.push(self.node(child));
+        }
+        if !after.comments.is_empty() {
+            docs.push(text(" "));
+            docs.push(after.inline());
+        }
+        Doc::Concat(docs)
+    }
+
+    /// Operands separated by the operator, continued on the next lines when they do not fit
+    fn chain(&self, pair: Pair<'a, Rule>, operator: &'static str) -> Doc {
+        let (children, after) = self.children(pair);
+        let mut children = children.into_iter();
+        let Some((gap, first)) = children.next() else {
+            return Doc::Concat(vec![]);
+        };
+        let mut rest = vec![];
+        for (mut gap, child) in children {
+            let same_line = trailing(gap.trailing());
+            rest.extend([
+                text(operator),
+                same_line,
+                Doc::Line,
+                gap.leading(),
+                self.node(child),
+            ]);
+        }
+        rest.push(after.inline());
+        group(Doc::Concat(vec![
+            gap.inline(),
+            self.node(first),
+            nest(Doc::Concat(rest)),
+        ]))
+    }
+
+    /// Assignment or ascription, with doc-comments on their own lines
+    fn entry(&self, pair: Pair<'a, Rule>) -> Doc {
+        let operator = if pair.as_rule() == Rule::assignment {
+            " = "
+        } else {
+            ": "
+        };
+        let (children, after) = self.children(pair);
+        let mut docs = vec![];
+        for (gap, child) in children {
+            docs.push(gap.leading());
+            match child.as_rule() {
+                Rule::doc_comment => docs.extend([text(child.as_str().trim_end()), Doc::HardLine]),
+                Rule::key => docs.extend([text(child.as_str()), text(operator)]),
+                _ => docs.push(self.node(child)),
+            }
+        }
+        docs.push(after.inline());
+        Doc::Concat(docs)
+    }
+
+    /// `let` with the bindings on one line when they fit, otherwise one per line, and the body after `in`
+    fn binding(&self, pair: Pair<'a, Rule>) -> Doc {
+        let (children, after) = self.children(pair);
+        let (mut gaps, mut items): (Vec<_>, Vec<_>) = children.into_iter().unzip();
+        let (Some(mut body_gap), Some(body)) = (gaps.pop(), items.pop()) else {
+            return Doc::Concat(vec![]);
+        };
+        let count = items.len();
+        let mut bindings = vec![];
+        for (idx, item) in items.into_iter().enumerate() {
+            let mut gap = std::mem::take(&mut gaps[idx]);
+            if idx == 0 {
+                gap.drop_empty_lines();
+            }
+            bindings.extend([Doc::Line, gap.leading(), self.node(item)]);
+            let last = idx + 1 == count;
+            bindings.push(if last { Doc::IfBreak(",") } else { text(",") });
+            let trail = match gaps.get_mut(idx + 1) {
+                Some(next) => next.trailing(),
+                None => body_gap.trailing(),
+            };
+            bindings.push(trailing(trail));
+        }
+        group(Doc::Concat(vec![
+            group(Doc::Concat(vec![
+                text("let"),
+                nest(Doc::Concat(bindings)),
+                Doc::Line,
+                text("in"),
+            ])),
+            Doc::Line,
+            body_gap.leading(),
+            self.node(body),
+            after.inline(),
+        ]))
+    }
+
+    /// Bracketed items separated by commas, one per line with a trailing comma when they do not fit
+    fn sequence(&self, pair: Pair<'a, Rule>, open: &'static str, close: &'static str) -> Doc {
+        let positional = matches!(pair.as_rule(), Rule::tuple | Rule::tuple_type);
+        let (children, mut after) = self.children(pair);
+        // a single positional item needs the separator to tell it from parentheses
+        let single = positional && children.len() == 1;
+        let mut items = vec![];
+        let mut gaps: Vec<Gap> = vec![];
+        for (gap, child) in children {
+            gaps.push(gap);
+            items.push(child);
+        }
+        gaps.push(Gap::default());
+        let mut docs = vec![Doc::SoftLine];
+        let count = items.len();
+        for (idx, item) in items.into_iter().enumerate() {
+            let mut gap = std::mem::take(&mut gaps[idx]);
+            if idx > 0 {
+                docs.push(Doc::Line);
+            } else {
+                gap.drop_empty_lines();
+            }
+            docs.push(gap.leading());
+            docs.push(self.node(item));
+            let last = idx + 1 == count;
+            docs.push(match (last, single) {
+                (false, _) | (true, true) => text(","),
+                (true, false) => Doc::IfBreak(","),
+            });
+            let trail = if last {
+                after.trailing()
+            } else {
+                gaps[idx + 1].trailing()
+            };
+            docs.push(trailing(trail));
+        }
+        for comment in after.comments {
+            docs.push(Doc::HardLine);
+            docs.push(text(comment.text));
+        }
+        group(Doc::Concat(vec![
+            text(open),
+            nest(Doc::Concat(docs)),
+            Doc::SoftLine,
+            text(close),
+        ]))
+    }
+}
+
+/// Lays out the document, choosing for every group whether it fits on the line
+fn render(doc: &Doc, options: &FormatOptions) -> String {
+    let mut printer = Printer::default();
+    let mut stack = vec![(0, false, doc)];
+    while let Some((indent, flat, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => printer.write(s),
+            Doc::IfBreak(s) if !flat => printer.write(s),
+            Doc::Line if flat => printer.write(" "),
+            Doc::HardLine | Doc::Line | Doc::SoftLine if !flat || matches!(doc, Doc::HardLine) => {
+                printer.newline(indent)
+            }
+            Doc::EmptyLine if !flat => printer.out.push('\n'),
+            Doc::Line | Doc::SoftLine | Doc::EmptyLine | Doc::IfBreak(_) | Doc::BreakParent => {}
+            Doc::HardLine => unreachable!("always breaks"),
+            Doc::Nest(doc) => stack.push((indent + options.indent, flat, doc)),
+            Doc::Group(inner) => {
+                let width = options.width.saturating_sub(printer.col);
+                let flat = flat || fits(width, inner, &stack);
+                stack.push((indent, flat, inner));
+            }
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, flat, doc))),
+        }
+    }
+    let out = printer
+        .out
+        .trim_start_matches('\n')
+        .trim_end_matches(['\n', ' ']);
+    format!("{out}\n")
+}
+
+/// Output with indentation written only before text, so lines have no trailing spaces
+#[derive(Default)]
+struct Printer {
+    out: String,
+    col: usize,
+    indent: Option<usize>,
+}
+
+impl Printer {
+    fn write(&mut self, s: &str) {
+        if let Some(indent) = self.indent.take() {
+            self.out.extend(std::iter::repeat_n(' ', indent));
+        }
+        self.out.push_str(s);
+        self.col = match s.rfind('\n') {
+            Some(pos) => s[pos + 1..].chars().count(),
+            None => self.col + s.chars().count(),
+        };
+    }
+
+    fn newline(&mut self, indent: usize) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+        self.out.push('\n');
+        self.indent = Some(indent);
+        self.col = indent;
+    }
+}
+
+/// Whether the group printed on a single line and everything after it up to the next line break fit into the `width`
+fn fits(width: usize, group: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut width = width as isize;
+    let mut stack: Vec<(bool, &Doc)> = rest.iter().map(|&(_, flat, doc)| (flat, doc)).collect();
+    stack.push((true, group));
+    while let Some((flat, doc)) = stack.pop() {
+        if width < 0 {
+            return false;
+        }
+        match doc {
+            Doc::Text(s) if s.contains('\n') => return false,
+            Doc::Text(s) => width -= s.chars().count() as isize,
+            Doc::Line if flat => width -= 1,
+            Doc::HardLine | Doc::BreakParent if flat => return false,
+            Doc::Line | Doc::SoftLine | Doc::HardLine | Doc::EmptyLine if !flat => return true,
+            Doc::IfBreak(s) if !flat => width -= s.len() as isize,
+            Doc::Nest(doc) | Doc::Group(doc) => stack.push((flat, doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (flat, doc))),
+            _ => {}
+        }
+    }
+    width >= 0
+}
+
+#[cfg(test)]
+use crate::parse::parse_str;
+
+#[cfg(test)]
+fn assert_formats(input: &str, expected: &str) {
+    let formatted = format_str(input).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format_str(&formatted).unwrap(), formatted, "not idempotent");
+    assert_eq!(
+        parse_str(&formatted).unwrap().term,
+        parse_str(input).unwrap().term,
+        "meaning changed"
+    );
+}
+
+#[test]
+fn format_examples() {
+    assert_formats("(a = 1; b = 2;)", "(a = 1, b = 2)\n");
+    assert_formats("{ x : #int; y:#text }", "{x: #int, y: #text}\n");
+    assert_formats("( 1 , )", "(1,)\n");
+    assert_formats("{#int;}", "{#int,}\n");
+    assert_formats("{ }", "{}\n");
+    assert_formats("\\{x: #int}=>x . y.0", "\\{x: #int} => x.y.0\n");
+    assert_formats("@ @x", "@ @x\n");
+    assert_formats("@ @@", "@ @@\n");
+    assert_formats("f x (a = 1)", "f x (a = 1)\n");
+    assert_formats("let a=1;b=a,in(x = b)", "let a = 1, b = a in (x = b)\n");
+    assert_formats("('let' = let x = 1 in x)", "('let' = let x = 1 in x)\n");
+    assert_formats("import/* base */'a.kers'", "import /* base */ 'a.kers'\n");
+    let hash = format!("sha256:{}", "0a".repeat(32));
+    let pinned = format!("import 'a.kers' {hash}\n");
+    assert_formats(&format!("import'a.kers'\n  {hash}"), &pinned);
+    assert_formats(
+        "{a : #int}->{b: #text}&{c: #int}",
+        "{a: #int} -> {b: #text} & {c: #int}\n",
+    );
+    assert_formats(
+        "('a ${ x } b', 0x1_F, r#\"raw\"#)",
+        "('a ${ x } b', 0x1_F, r#\"raw\"#)\n",
+    );
+}
+
+#[test]
+fn format_breaks_long_lines() {
+    assert_formats(
+        "(a = 1, b = (c = 'some long text here', d = 'another long text here', e = 12345678901234))",
+        "(\n  a = 1,\n  b = (\n    c = 'some long text here',\n    d = 'another long text here',\n    e = 12345678901234,\n  ),\n)\n",
+    );
+    assert_formats(
+        "let base = (host = 'localhost', port = 8080) in (a = base, b = (host = base.host, port = 1))",
+        "let base = (host = 'localhost', port = 8080) in\n(a = base, b = (host = base.host, port = 1))\n",
+    );
+    assert_formats(
+        "let /// shared\n base = (host = 'a-long-host-name.example.com', port = 8080), // base\n other = 1 in other",
+        "let\n  /// shared\n  base = (host = 'a-long-host-name.example.com', port = 8080), // base\n  other = 1,\nin\nother\n",
+    );
+    let narrow = FormatOptions {
+        width: 10,
+        indent: 4,
+    };
+    assert_eq!(
+        format_named("narrow", "{a: #int, b: #text}", &narrow).unwrap(),
+        "{\n    a: #int,\n    b: #text,\n}\n"
+    );
+    assert_eq!(
+        format_named("narrow", "function argument", &narrow).unwrap(),
+        "function\n    argument\n"
+    );
+    assert_formats(
+        "(a = \"\"\"\n  multi\n  line\n\"\"\", b = 2)",
+        "(\n  a = \"\"\"\n  multi\n  line\n\"\"\",\n  b = 2,\n)\n",
+    );
+}
+
+#[test]
+fn format_preserves_comments() {
+    assert_formats(
+        "// head\n(\n\n  a = 1, // one\n\n\n  // about b\n  b = 2; /* end */\n  // dangling\n)\n// tail",
+        "// head\n(\n  a = 1, // one\n\n  // about b\n  b = 2, /* end */\n  // dangling\n)\n// tail\n",
+    );
+    assert_formats("(a = 1 /* one */, b = 2)", "(a = 1, /* one */ b = 2)\n");
+    assert_formats(
+        "(a = 1, // one\nb = 2)",
+        "(\n  a = 1, // one\n  b = 2,\n)\n",
+    );
+    assert_formats(
+        "(\n  /// Documented\n  a = 1)",
+        "(\n  /// Documented\n  a = 1,\n)\n",
+    );
+    assert_formats("f /* arg */ x", "f /* arg */ x\n");
+    assert_formats("f // arg\n x", "f // arg\n  x\n");
+    assert_formats("(a = 1) // done", "(a = 1) // done\n");
+}
+
+#[test]
+fn format_rejects_syntax_errors() {
+    assert!(format_str("(a = ").is_err());
+}
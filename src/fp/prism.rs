@@ -34,14 +34,14 @@ pub trait Prism: Sized + Copy {
 pub struct ToLeft<A, B>(PhantomData<A>, PhantomData<B>);
 impl<A, B> Clone for ToLeft<A, B> {
     fn clone(&self) -> Self {
-        ToLeft(PhantomData, PhantomData)
+        *self
     }
 }
 impl<A, B> Copy for ToLeft<A, B> {}
 pub struct ToRight<A, B>(PhantomData<A>, PhantomData<B>);
 impl<A, B> Clone for ToRight<A, B> {
     fn clone(&self) -> Self {
-        ToRight(PhantomData, PhantomData)
+        *self
     }
 }
 
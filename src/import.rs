@@ -0,0 +1,186 @@
+use std::{fs, io, path::Path as FilePath, sync::Arc};
+
+use serde_json::Value as Json;
+use thiserror::Error;
+
+use crate::{export::Path, AsTyp, Float, Key, PrimType, Primitive, Term, ToTerm, Type};
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("can not read {name}: {source}")]
+    Io { name: String, source: io::Error },
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("at {path}: {what} has no kers representation")]
+    Unsupported { path: Path, what: &'static str },
+}
+
+/// Builds the term from the JSON document
+///
+/// Objects become records of `Term::Set`s joined with `Term::Append`, arrays become records keyed by indices,
+/// numbers and strings become primitives and `null` becomes the empty record `()`.
+/// Booleans and integers out of the `#int` range have no counterpart in kers and are reported as errors.
+pub fn from_json(text: &str) -> Result<Arc<Term>, ImportError> {
+    from_json_value(&serde_json::from_str(text)?)
+}
+
+/// Builds the term from the JSON file, as described in [`from_json`]
+pub fn from_json_file(path: impl AsRef<FilePath>) -> Result<Arc<Term>, ImportError> {
+    let path = path.as_ref();
+    match fs::read_to_string(path) {
+        Ok(text) => from_json(&text),
+        Err(source) => Err(ImportError::Io {
+            name: path.display().to_string(),
+            source,
+        }),
+    }
+}
+
+/// Builds the term from the parsed JSON tree, as described in [`from_json`]
+pub fn from_json_value(json: &Json) -> Result<Arc<Term>, ImportError> {
+    term(json, &Path::default()).map(Arc::new)
+}
+
+/// Record type of the term built from the JSON tree, an intersection of `Type::Field`s for objects and arrays
+pub fn json_type(json: &Json) -> Result<Arc<Term>, ImportError> {
+    typ(json, &Path::default()).map(Arc::new)
+}
+
+fn term(json: &Json, path: &Path) -> Result<Term, ImportError> {
+    match json {
+        Json::Null => Ok(Term::Empty),
+        Json::Bool(_) => Err(unsupported(path, "a boolean")),
+        Json::Number(n) => number(n, path).map(ToTerm::to_term),
+        Json::String(s) => Ok(s.as_str().to_term()),
+        Json::Array(elems) => {
+            let mut fields = vec![];
+            for (idx, elem) in elems.iter().enumerate() {
+                let key = Key::Index(idx);
+                let value = term(elem, &path.with(&key))?.to_arc_term();
+                fields.push(Term::Set { name: key, value });
+            }
+            Ok(fields.as_slice().to_term())
+        }
+        Json::Object(object) => {
+            let mut fields = vec![];
+            for (name, value) in object {
+                let key = Key::Name(name.clone());
+                fields.push((name, term(value, &path.with(&key))?).to_term());
+            }
+            Ok(fields.as_slice().to_term())
+        }
+    }
+}
+
+fn typ(json: &Json, path: &Path) -> Result<Term, ImportError> {
+    let fields = match json {
+        Json::Null => return Ok(PrimType::Any.to_term()),
+        Json::Bool(_) => return Err(unsupported(path, "a boolean")),
+        Json::Number(n) => {
+            let prim = match number(n, path)? {
+                Primitive::Long(_) => PrimType::Long,
+                _ => PrimType::Float,
+            };
+            return Ok(prim.to_term());
+        }
+        Json::String(_) => return Ok(PrimType::Text.to_term()),
+        Json::Array(elems) => {
+            let mut fields = vec![];
+            for (idx, elem) in elems.iter().enumerate() {
+                let key = Key::Index(idx);
+                let typ = typ(elem, &path.with(&key))?.to_arc_term();
+                fields.push(Type::Field { name: key, typ }.to_term());
+            }
+            fields
+        }
+        Json::Object(object) => {
+            let mut fields = vec![];
+            for (name, value) in object {
+                let key = Key::Name(name.clone());
+                fields.push(AsTyp((name, typ(value, &path.with(&key))?)).to_term());
+            }
+            fields
+        }
+    };
+    if fields.is_empty() {
+        return Ok(PrimType::Any.to_term());
+    }
+    Ok(AsTyp(fields.as_slice()).to_term())
+}
+
+/// Integers become `#int`s, the ones out of its range are reported rather than rounded to floats
+fn number(n: &serde_json::Number, path: &Path) -> Result<Primitive, ImportError> {
+    if let Some(n) = n.as_i64() {
+        return Ok(Primitive::Long(n));
+    }
+    if n.is_u64() {
+        return Err(unsupported(path, "an integer out of the #int range"));
+    }
+    match n.as_f64() {
+        Some(x) => Ok(Primitive::Float(Float(x))),
+        None => Err(unsupported(path, "a number out of range")),
+    }
+}
+
+fn unsupported(path: &Path, what: &'static str) -> ImportError {
+    ImportError::Unsupported {
+        path: path.clone(),
+        what,
+    }
+}
+
+#[cfg(test)]
+use crate::{
+    evaltime::{checking::TypeChecking, evaluate::Evaluation, values::Value},
+    parse::parse_term,
+};
+
+#[test]
+fn import_json() {
+    let json = r#"{"name": "kers", "version": [0, 1], "ratio": 0.5, "none": null, "empty": {}}"#;
+    let expected =
+        parse_term("(name = 'kers', version = (0, 1), ratio = 0.5, none = (), empty = ())");
+    assert_eq!(from_json(json).unwrap(), expected.unwrap());
+    assert_eq!(from_json("[]").unwrap(), Arc::new(Term::Empty));
+    assert_eq!(from_json("1e300").unwrap(), 1e300.to_arc_term());
+}
+
+#[test]
+fn import_json_type() {
+    let json: Json =
+        serde_json::from_str(r#"{"name": "kers", "version": [0, 1.5], "none": null}"#).unwrap();
+    let expected = parse_term("{name: #text, version: {#int, #float}, none: {}}").unwrap();
+    assert_eq!(json_type(&json).unwrap(), expected);
+    let term = from_json_value(&json).unwrap();
+    let typ = Evaluation::new(())
+        .eval(&expected, &Value::empty())
+        .unwrap();
+    let checked = TypeChecking::new(()).check_against(&term, &Value::empty(), &typ);
+    assert_eq!(checked.unwrap().to_string(), typ.to_string());
+}
+
+#[test]
+fn import_json_errors() {
+    let err = from_json(r#"{"server": {"debug": true}}"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .server.debug: a boolean has no kers representation"
+    );
+    let err = from_json(r#"{"ids": [1, 9223372036854775808]}"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .ids.1: an integer out of the #int range has no kers representation"
+    );
+    let json: Json = serde_json::from_str("18446744073709551615").unwrap();
+    assert!(json_type(&json).is_err());
+    assert_eq!(
+        from_json("-9223372036854775808").unwrap(),
+        i64::MIN.to_arc_term()
+    );
+    let err = from_json(r#"{"server": "#).unwrap_err();
+    assert!(matches!(err, ImportError::Json(_)), "{err}");
+    assert!(matches!(
+        from_json_file("missing.json"),
+        Err(ImportError::Io { .. })
+    ));
+}
@@ -1 +1,2 @@
-pub mod term;
\ No newline at end of file
+pub mod source;
+pub mod term;
@@ -0,0 +1,124 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::Term;
+
+/// Identifier of a source file, assigned by whoever is loading the sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub usize);
+
+/// Location of a piece of source: byte range and the line and column of its start, both starting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    pub(crate) fn from_pest(file: FileId, span: pest::Span) -> Self {
+        let (line, col) = span.start_pos().line_col();
+        Span {
+            file,
+            start: span.start(),
+            end: span.end(),
+            line,
+            col,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// Identity of a term node, valid while the `Arc` holding the node is alive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    pub fn of(term: &Term) -> Self {
+        NodeId(term as *const Term as usize)
+    }
+}
+
+/// Spans of the term nodes produced by the parser, along with doc-comments of record entries.
+/// Keeps the parsed term alive so node identities stay valid.
+#[derive(Debug, Clone, Default)]
+pub struct Spans {
+    root: Option<Arc<Term>>,
+    spans: HashMap<NodeId, Span>,
+    docs: HashMap<NodeId, String>,
+}
+
+impl Spans {
+    pub fn get(&self, term: &Term) -> Option<Span> {
+        self.spans.get(&NodeId::of(term)).copied()
+    }
+
+    pub fn get_node(&self, node: NodeId) -> Option<Span> {
+        self.spans.get(&node).copied()
+    }
+
+    /// Doc-comment attached to an assignment or an ascription
+    pub fn doc(&self, term: &Term) -> Option<&str> {
+        self.docs.get(&NodeId::of(term)).map(String::as_str)
+    }
+
+    pub fn root(&self) -> Option<&Arc<Term>> {
+        self.root.as_ref()
+    }
+
+    pub(crate) fn set_root(&mut self, root: Arc<Term>) {
+        self.root = Some(root);
+    }
+
+    pub(crate) fn set(&mut self, term: &Term, span: Span) {
+        self.spans.insert(NodeId::of(term), span);
+    }
+
+    pub(crate) fn set_doc(&mut self, term: &Term, doc: String) {
+        self.docs.insert(NodeId::of(term), doc);
+    }
+
+    /// Assigns `span` to the `term` and all its descendants not having a span yet
+    pub(crate) fn mark(&mut self, term: &Term, span: Span) {
+        if self.spans.contains_key(&NodeId::of(term)) {
+            return;
+        }
+        self.spans.insert(NodeId::of(term), span);
+        for child in term.children() {
+            self.mark(child, span);
+        }
+    }
+}
+
+/// A named source text
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+/// Source texts indexed by their file ids
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    files: Vec<SourceFile>,
+}
+
+impl Sources {
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> FileId {
+        self.files.push(SourceFile {
+            name: name.into(),
+            text: text.into(),
+        });
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file.0)
+    }
+}
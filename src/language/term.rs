@@ -1,8 +1,12 @@
+mod print;
 mod to_term;
 
+pub use self::print::PrintError;
 pub use self::to_term::{AsTyp, ToTerm};
+#[cfg(test)]
+pub(crate) use print::arb_term;
 use derive_more::From;
-use std::sync::Arc;
+use std::{fmt, sync::Arc};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum GenType<T> {
@@ -16,16 +20,53 @@ pub enum GenType<T> {
 pub enum PrimType {
     Text,
     Long,
+    Float,
     Universe,
     Any,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Primitive {
-    Long(u64),
+    Long(i64),
+    Float(Float),
     Text(String),
 }
 
+/// Floating point number compared and hashed by its bits, so it can be a part of a term
+#[derive(Debug, Clone, Copy)]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Float {}
+
+impl std::hash::Hash for Float {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+/// SHA-256 hash of the normal form of a term, pinning the contents of an import
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest(pub [u8; 32]);
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sha256:")?;
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
 pub type Type = GenType<Term>;
 pub type NormalType = GenType<Type>;
 
@@ -35,10 +76,11 @@ pub enum Key {
     Index(usize),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub enum Term {
     Type(Type),
     Prim(Primitive),
+    #[default]
     Empty,
     Append { left: Arc<Term>, right: Arc<Term> },
     Set { name: Key, value: Arc<Term> },
@@ -46,13 +88,10 @@ pub enum Term {
     Lambda { dom: Arc<Term>, body: Arc<Term> },
     Unlambda(Arc<Term>),
     Then { first: Arc<Term>, next: Arc<Term> },
+    Let { name: Key, value: Arc<Term>, body: Arc<Term> },
     Reflect,
-}
-
-impl Default for Term {
-    fn default() -> Self {
-        Term::Empty
-    }
+    Interpolate(Vec<Arc<Term>>),
+    Import { path: String, hash: Option<Box<Digest>> },
 }
 
 impl Term {
@@ -66,4 +105,24 @@ impl Term {
             next: Term::Unlambda(func).to_arc_term(),
         }
     }
+
+    pub fn children(&self) -> Vec<&Arc<Term>> {
+        match self {
+            Term::Type(Type::Prim(_))
+            | Term::Prim(_)
+            | Term::Empty
+            | Term::Get(_)
+            | Term::Reflect
+            | Term::Import { .. } => vec![],
+            Term::Type(Type::Field { typ, .. }) => vec![typ],
+            Term::Type(Type::Function { dom, codom }) => vec![dom, codom],
+            Term::Type(Type::And { left, right }) | Term::Append { left, right } => vec![left, right],
+            Term::Set { value, .. } => vec![value],
+            Term::Lambda { dom, body } => vec![dom, body],
+            Term::Unlambda(func) => vec![func],
+            Term::Then { first, next } => vec![first, next],
+            Term::Let { value, body, .. } => vec![value, body],
+            Term::Interpolate(parts) => parts.iter().collect(),
+        }
+    }
 }
@@ -0,0 +1,636 @@
+//! Terms in the surface syntax
+//!
+//! [`Term::to_source`] gives the text parsing back to the same term. The grammar has no syntax
+//! for some terms built in code: records merged with terms which are not entries, names which
+//! are not identifiers and index keys outside of tuples, printing them fails.
+//! [`Display`](fmt::Display) shows such terms by their `Debug` form.
+use std::fmt::{self, Write};
+
+use thiserror::Error;
+
+use super::{GenType, Key, PrimType, Primitive, Term, Type};
+
+/// Term the grammar has no syntax for
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PrintError {
+    #[error("no syntax for merging a record with a term which is not an entry")]
+    Merge,
+    #[error("no syntax for the name {0}, it is not an identifier")]
+    Name(Key),
+    #[error("no syntax for the index {0} outside of a tuple")]
+    Index(usize),
+}
+
+type Printed = Result<String, PrintError>;
+
+/// Binding strength of the syntactic forms, from the loosest to the tightest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    /// `\dom => body` and `let name = value in body`
+    Lambda,
+    /// `dom -> codom`
+    Arrow,
+    /// `left & right`
+    And,
+    /// `func arg`
+    Apply,
+    /// `first.next`
+    Chain,
+    /// `@func`
+    Modified,
+    /// literals, names, records and parenthesized terms
+    Atom,
+}
+
+impl Term {
+    /// Source text parsing back to the term, fails on terms the grammar has no syntax for
+    pub fn to_source(&self) -> Result<String, PrintError> {
+        print(self, Prec::Lambda)
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_source() {
+            Ok(text) => f.write_str(&text),
+            Err(_) => write!(f, "{self:?}"),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match print_type(self) {
+            Ok((_, text)) => f.write_str(&text),
+            Err(_) => write!(f, "{self:?}"),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Name(name) if is_identifier(name) => f.write_str(name),
+            Key::Name(name) => f.write_str(&quoted(name)),
+            Key::Index(idx) => write!(f, "{idx}"),
+        }
+    }
+}
+
+impl fmt::Display for Primitive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Primitive::Long(n) => write!(f, "{n}"),
+            Primitive::Float(x) => write!(f, "{x}"),
+            Primitive::Text(s) => f.write_str(&quoted(s)),
+        }
+    }
+}
+
+/// Prints the `term` so it can be used where a term binding at least as strong as `prec` is expected
+fn print(term: &Term, prec: Prec) -> Printed {
+    let (own, text) = match term {
+        Term::Type(typ) => print_type(typ)?,
+        Term::Prim(prim) => (Prec::Atom, prim.to_string()),
+        Term::Empty => (Prec::Atom, "()".to_string()),
+        Term::Append { .. } | Term::Set { .. } => (Prec::Atom, record(term)?),
+        Term::Get(Key::Name(name)) if is_identifier(name) => (Prec::Atom, name.clone()),
+        Term::Get(key) => return Err(no_syntax(key)),
+        Term::Lambda { dom, body } => {
+            let dom = print(dom, Prec::Chain)?;
+            (
+                Prec::Lambda,
+                format!("\\{dom} => {}", print(body, Prec::Lambda)?),
+            )
+        }
+        Term::Unlambda(func) => {
+            let func = print(func, Prec::Modified)?;
+            // `@@` is the reflection, not the modifier applied twice
+            let space = if func.starts_with('@') { " " } else { "" };
+            (Prec::Modified, format!("@{space}{func}"))
+        }
+        Term::Then { first, next } => match &**next {
+            Term::Unlambda(func) => {
+                let func = print(func, Prec::Apply)?;
+                (
+                    Prec::Apply,
+                    format!("{func} {}", print(first, Prec::Chain)?),
+                )
+            }
+            _ => (
+                Prec::Chain,
+                format!("{}.{}", chain_head(first)?, chain_link(next)?),
+            ),
+        },
+        Term::Let { name, value, body } => {
+            let name = key(name)?;
+            let value = print(value, Prec::Lambda)?;
+            (
+                Prec::Lambda,
+                format!("let {name} = {value} in {}", print(body, Prec::Lambda)?),
+            )
+        }
+        Term::Reflect => (Prec::Atom, "@@".to_string()),
+        Term::Interpolate(parts) => (Prec::Atom, interpolation(parts)?),
+        Term::Import { path, hash } => {
+            let hash = hash
+                .as_ref()
+                .map(|hash| format!(" {hash}"))
+                .unwrap_or_default();
+            (Prec::Atom, format!("import {}{hash}", quoted(path)))
+        }
+    };
+    if own < prec {
+        Ok(format!("({text})"))
+    } else {
+        Ok(text)
+    }
+}
+
+fn print_type(typ: &Type) -> Result<(Prec, String), PrintError> {
+    let printed = match typ {
+        GenType::Prim(prim) => {
+            let text = match prim {
+                PrimType::Text => "#text",
+                PrimType::Long => "#int",
+                PrimType::Float => "#float",
+                PrimType::Universe => "*",
+                PrimType::Any => "{}",
+            };
+            (Prec::Atom, text.to_string())
+        }
+        GenType::Function { dom, codom } => {
+            let dom = print(dom, Prec::And)?;
+            (
+                Prec::Arrow,
+                format!("{dom} -> {}", print(codom, Prec::Arrow)?),
+            )
+        }
+        GenType::Field { .. } | GenType::And { .. } => match record_type(typ)? {
+            Some(text) => (Prec::Atom, text),
+            None => match typ {
+                GenType::And { left, right } => {
+                    let left = print(left, Prec::And)?;
+                    (
+                        Prec::And,
+                        format!("{left} & {}", print(right, Prec::Apply)?),
+                    )
+                }
+                GenType::Field { name, .. } => return Err(no_syntax(name)),
+                _ => unreachable!("only fields are intersected"),
+            },
+        },
+    };
+    Ok(printed)
+}
+
+/// Key of an entry outside of a tuple
+fn key(name: &Key) -> Printed {
+    match name {
+        Key::Name(_) => Ok(name.to_string()),
+        Key::Index(idx) => Err(PrintError::Index(*idx)),
+    }
+}
+
+/// Error for a name or an index used where there is no syntax for it
+fn no_syntax(key: &Key) -> PrintError {
+    match key {
+        Key::Name(_) => PrintError::Name(key.clone()),
+        Key::Index(idx) => PrintError::Index(*idx),
+    }
+}
+
+/// The term before a `.`, where an integer literal would become a float,
+/// digits after a dot are either a positional field or a fraction and are safe
+fn chain_head(first: &Term) -> Printed {
+    let text = print(first, Prec::Chain)?;
+    let digits = text.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_');
+    let ends_with_number = digits.len() < text.len()
+        && !digits.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '.');
+    if ends_with_number {
+        Ok(format!("({text})"))
+    } else {
+        Ok(text)
+    }
+}
+
+/// The term after a `.`, where a number would be read as a positional field
+fn chain_link(next: &Term) -> Printed {
+    match next {
+        Term::Get(Key::Index(idx)) => Ok(idx.to_string()),
+        Term::Prim(Primitive::Long(_) | Primitive::Float(_)) => Ok(format!("({next})")),
+        _ => print(next, Prec::Modified),
+    }
+}
+
+/// Terms merged by `Append`, in the order they are written
+fn merged(term: &Term) -> Vec<&Term> {
+    match term {
+        Term::Append { left, right } => {
+            let mut items = merged(left);
+            items.push(right);
+            items
+        }
+        other => vec![other],
+    }
+}
+
+fn record(term: &Term) -> Printed {
+    let items = merged(term);
+    let positional = items
+        .iter()
+        .enumerate()
+        .all(|(idx, item)| matches!(item, Term::Set { name: Key::Index(i), .. } if *i == idx));
+    let items = items
+        .into_iter()
+        .map(|item| match item {
+            Term::Set { value, .. } if positional => print(value, Prec::Lambda),
+            Term::Set { name, value } => {
+                Ok(format!("{} = {}", key(name)?, print(value, Prec::Lambda)?))
+            }
+            _ => Err(PrintError::Merge),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(enclose('(', &items, positional, ')'))
+}
+
+/// Types intersected by `And`, in the order they are written
+fn intersected(term: &Term) -> Vec<&Term> {
+    match term {
+        Term::Type(GenType::And { left, right }) => {
+            let mut items = intersected(left);
+            items.push(right);
+            items
+        }
+        other => vec![other],
+    }
+}
+
+/// Braced syntax for the intersection of fields, if it is one,
+/// index keys are written only in tuple types
+fn record_type(typ: &Type) -> Result<Option<String>, PrintError> {
+    let fields = match typ {
+        GenType::Field { name, typ } => vec![(name, typ)],
+        GenType::And { left, right } => {
+            let mut items = intersected(left);
+            items.push(right);
+            let fields = items.into_iter().map(|item| match item {
+                Term::Type(GenType::Field { name, typ }) => Some((name, typ)),
+                _ => None,
+            });
+            match fields.collect::<Option<_>>() {
+                Some(fields) => fields,
+                None => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    let positional = fields
+        .iter()
+        .enumerate()
+        .all(|(idx, (name, _))| **name == Key::Index(idx));
+    if !positional && fields.iter().any(|(name, _)| matches!(name, Key::Index(_))) {
+        return Ok(None);
+    }
+    let items = fields
+        .into_iter()
+        .map(|(name, typ)| match positional {
+            true => print(typ, Prec::Lambda),
+            false => Ok(format!("{name}: {}", print(typ, Prec::Lambda)?)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(enclose('{', &items, positional, '}')))
+}
+
+/// Comma separated items, a single positional item is followed by a comma to tell it from a parenthesized term
+fn enclose(open: char, items: &[String], positional: bool, close: char) -> String {
+    let trailing = if positional && items.len() == 1 {
+        ","
+    } else {
+        ""
+    };
+    format!("{open}{}{trailing}{close}", items.join(", "))
+}
+
+/// Double quoted string, texts are written literally unless they would be merged with the neighbouring text
+fn interpolation(parts: &[std::sync::Arc<Term>]) -> Printed {
+    let embeds = parts
+        .iter()
+        .any(|part| !matches!(**part, Term::Prim(Primitive::Text(_))));
+    let mut out = String::from('"');
+    let mut after_text = false;
+    for part in parts {
+        match &**part {
+            Term::Prim(Primitive::Text(text)) if embeds && !after_text && !text.is_empty() => {
+                for c in text.chars() {
+                    escape(&mut out, c, '"');
+                }
+                after_text = true;
+            }
+            part => {
+                let _ = write!(out, "${{{}}}", print(part, Prec::Lambda)?);
+                after_text = false;
+            }
+        }
+    }
+    out.push('"');
+    Ok(out)
+}
+
+/// Words which are not names of fields unless quoted
+const KEYWORDS: [&str; 3] = ["let", "in", "import"];
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    !KEYWORDS.contains(&name)
+        && chars.next().is_some_and(char::is_alphabetic)
+        && chars.all(|c| c.is_alphabetic() || c.is_ascii_digit() || c == '_')
+}
+
+fn quoted(s: &str) -> String {
+    let quote = if s.contains('\'') && !s.contains('"') {
+        '"'
+    } else {
+        '\''
+    };
+    let mut out = String::from(quote);
+    for c in s.chars() {
+        escape(&mut out, c, quote);
+    }
+    out.push(quote);
+    out
+}
+
+fn escape(out: &mut String, c: char, quote: char) {
+    match c {
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\x08' => out.push_str("\\b"),
+        '\x0c' => out.push_str("\\f"),
+        c if c == quote => {
+            out.push('\\');
+            out.push(c)
+        }
+        '$' if quote == '"' => out.push_str("\\$"),
+        c if c.is_control() => {
+            let _ = write!(out, "\\u{:04x}", c as u32);
+        }
+        c => out.push(c),
+    }
+}
+
+#[cfg(test)]
+use {
+    super::{Digest, Float, ToTerm},
+    crate::parse::parse_term,
+    proptest::prelude::*,
+    std::sync::Arc,
+};
+
+#[test]
+fn print_examples() {
+    let examples = [
+        "(greet = 'Hello', target = \"it's\", 'my key' = 38, '1' = -2.5)",
+        "(1, 'two', (3,))",
+        "{name: #text, port: #int, ratio: #float, any: {}, kind: *}",
+        "{#int, #text}",
+        "{a: #int} -> {b: #text} -> #float",
+        "({a: #int} -> #text) -> {}",
+        "x & {a: #int} & {b: #text, c: *}",
+        "{a: #int} & x",
+        "\\{x: #int} => f (x = x.0) y",
+        "f (\\x => x) (g y).z",
+        "@f @ @g @@",
+        "x.a.0.(1).'quoted key'.(f y).@@",
+        "(1).a",
+        "\"Hello, ${target}! \\${not interpolated}${'literal'}\"",
+        "(a = \"${x.b}\", 'q\"uote' = '\\n\\u0001')",
+        "let a = 1 in let 'in' = \\x => x in f (let b = a in b).'in'",
+        "(x = let a = 1 in a).('let' = letter)",
+        "import 'base.kers'.port",
+        "(a = import \"it's.json\", 'import' = importer)",
+    ];
+    for example in examples {
+        let term = parse_term(example).unwrap();
+        assert_eq!(term.to_string(), example);
+    }
+}
+
+#[test]
+fn print_without_syntax_sugar() {
+    let term = Term::Then {
+        first: 1i64.to_arc_term(),
+        next: 2i64.to_arc_term(),
+    };
+    assert_eq!(term.to_string(), "(1).(2)");
+    let term = Term::Interpolate(vec!["a".to_arc_term(), "b".to_arc_term()]);
+    assert_eq!(term.to_string(), "\"${'a'}${'b'}\"");
+    assert_eq!(Key::Index(2).to_string(), "2");
+    assert_eq!(Key::Name("it's".into()).to_string(), "\"it's\"");
+    assert_eq!(Key::Name("let".into()).to_string(), "'let'");
+    assert_eq!(Primitive::Float(Float(1e100)).to_string(), "1e100");
+}
+
+#[test]
+fn print_terms_without_syntax() {
+    let merge = Term::Append {
+        left: Term::get("x").to_arc_term(),
+        right: [("a", 1i64)].to_arc_term(),
+    };
+    assert_eq!(merge.to_source(), Err(PrintError::Merge));
+    assert!(merge.to_string().starts_with("Append {"));
+    let index = Term::Set {
+        name: Key::Index(1),
+        value: 1i64.to_arc_term(),
+    };
+    assert_eq!(index.to_source(), Err(PrintError::Index(1)));
+    let name = Term::Then {
+        first: Term::get("x").to_arc_term(),
+        next: Term::Get(Key::Name("a b".into())).to_arc_term(),
+    };
+    let err = name.to_source().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "no syntax for the name 'a b', it is not an identifier"
+    );
+    assert_eq!(
+        Term::Get(Key::Index(2)).to_source(),
+        Err(PrintError::Index(2))
+    );
+    let field = |idx| Type::Field {
+        name: Key::Index(idx),
+        typ: PrimType::Long.to_arc_term(),
+    };
+    assert_eq!(
+        field(1).to_arc_term().to_source(),
+        Err(PrintError::Index(1))
+    );
+    // the intersection of tuple types is written as one
+    let term = Type::And {
+        left: field(0).to_arc_term(),
+        right: field(0).to_arc_term(),
+    };
+    assert_eq!(term.to_string(), "{#int,} & {#int,}");
+    assert_eq!(parse_term("{#int,} & {#int,}").unwrap(), term.to_arc_term());
+}
+
+#[cfg(test)]
+fn arb_key(wide: bool) -> BoxedStrategy<Key> {
+    let names = prop_oneof![
+        arb_name(),
+        prop_oneof![Just("let"), Just("in")].prop_map(str::to_string),
+        "[ -~é\n\t]{0,4}",
+    ]
+    .prop_map(Key::Name);
+    match wide {
+        true => prop_oneof![names, (0usize..3).prop_map(Key::Index)].boxed(),
+        false => names.boxed(),
+    }
+}
+
+#[cfg(test)]
+fn arb_name() -> impl Strategy<Value = String> {
+    "[a-zA-Zé][a-zA-Z0-9_é]{0,4}".prop_filter("keywords are not names", |name| is_identifier(name))
+}
+
+/// Any terms, including the ones built in code which have no syntax
+#[cfg(test)]
+pub(crate) fn arb_term() -> impl Strategy<Value = Arc<Term>> {
+    terms(true)
+}
+
+/// Terms which have a syntax, the ones the parser builds
+#[cfg(test)]
+fn arb_syntax_term() -> impl Strategy<Value = Arc<Term>> {
+    terms(false)
+}
+
+#[cfg(test)]
+fn terms(wide: bool) -> impl Strategy<Value = Arc<Term>> {
+    let float = prop::num::f64::NORMAL | prop::num::f64::ZERO | prop::num::f64::SUBNORMAL;
+    let get = match wide {
+        true => arb_key(true).prop_map(Term::Get).boxed(),
+        false => arb_name()
+            .prop_map(|name| Term::Get(Key::Name(name)))
+            .boxed(),
+    };
+    let leaf = prop_oneof![
+        Just(Term::Empty),
+        Just(Term::Reflect),
+        any::<i64>().prop_map(|n| n.to_term()),
+        float.prop_map(|x| x.to_term()),
+        "[ -~é\n\t\u{1}]{0,6}".prop_map(|s| s.to_term()),
+        get,
+        ("[ -~é]{0,6}", prop::option::of(any::<[u8; 32]>())).prop_map(|(path, hash)| {
+            Term::Import {
+                path,
+                hash: hash.map(|hash| Box::new(Digest(hash))),
+            }
+        }),
+        prop_oneof![
+            Just(PrimType::Text),
+            Just(PrimType::Long),
+            Just(PrimType::Float),
+            Just(PrimType::Universe),
+            Just(PrimType::Any),
+        ]
+        .prop_map(|prim| prim.to_term()),
+    ]
+    .prop_map(Arc::new);
+    leaf.prop_recursive(4, 48, 4, move |inner| {
+        let pair = (inner.clone(), inner.clone());
+        let entries = || prop::collection::vec((arb_key(wide), inner.clone()), 1..4);
+        let elems = prop::collection::vec(inner.clone(), 1..4);
+        let syntax = prop_oneof![
+            entries().prop_map(|entries| {
+                let sets = entries
+                    .into_iter()
+                    .map(|(name, value)| Term::Set { name, value });
+                sets.map(ToTerm::to_arc_term)
+                    .reduce(|left, right| Term::Append { left, right }.to_arc_term())
+                    .unwrap()
+            }),
+            elems.clone().prop_map(|elems| {
+                let sets = elems.into_iter().enumerate().map(|(idx, value)| Term::Set {
+                    name: Key::Index(idx),
+                    value,
+                });
+                sets.map(ToTerm::to_arc_term)
+                    .reduce(|left, right| Term::Append { left, right }.to_arc_term())
+                    .unwrap()
+            }),
+            entries().prop_map(|entries| {
+                let fields = entries
+                    .into_iter()
+                    .map(|(name, typ)| Type::Field { name, typ });
+                fields
+                    .map(|field| field.to_arc_term())
+                    .reduce(|left, right| Type::And { left, right }.to_arc_term())
+                    .unwrap()
+            }),
+            elems.prop_map(|elems| {
+                let fields = elems.into_iter().enumerate().map(|(idx, typ)| Type::Field {
+                    name: Key::Index(idx),
+                    typ,
+                });
+                fields
+                    .map(|field| field.to_arc_term())
+                    .reduce(|left, right| Type::And { left, right }.to_arc_term())
+                    .unwrap()
+            }),
+            pair.clone()
+                .prop_map(|(dom, body)| Term::Lambda { dom, body }.to_arc_term()),
+            inner
+                .clone()
+                .prop_map(|func| Term::Unlambda(func).to_arc_term()),
+            pair.clone()
+                .prop_map(|(first, next)| Term::Then { first, next }.to_arc_term()),
+            (inner.clone(), 0usize..3).prop_map(|(first, idx)| {
+                let next = Term::Get(Key::Index(idx)).to_arc_term();
+                Term::Then { first, next }.to_arc_term()
+            }),
+            (arb_key(wide), pair.clone()).prop_map(|(name, (value, body))| {
+                Term::Let { name, value, body }.to_arc_term()
+            }),
+            pair.clone()
+                .prop_map(|(func, arg)| Term::apply(func, arg).to_arc_term()),
+            pair.clone()
+                .prop_map(|(dom, codom)| Type::Function { dom, codom }.to_arc_term()),
+            pair.clone()
+                .prop_map(|(left, right)| Type::And { left, right }.to_arc_term()),
+            prop::collection::vec(inner, 1..4)
+                .prop_map(|parts| Term::Interpolate(parts).to_arc_term()),
+        ];
+        let merges = pair.prop_map(|(left, right)| Term::Append { left, right }.to_arc_term());
+        match wide {
+            true => prop_oneof![14 => syntax, 1 => merges].boxed(),
+            false => syntax.boxed(),
+        }
+    })
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1000))]
+
+    #[test]
+    fn print_parse_roundtrip(term in arb_syntax_term()) {
+        let printed = term.to_source();
+        prop_assert!(printed.is_ok(), "{term:?}: {}", printed.unwrap_err());
+        let printed = printed.unwrap();
+        let parsed = parse_term(&printed);
+        prop_assert!(parsed.is_ok(), "{printed}: {}", parsed.unwrap_err());
+        prop_assert_eq!(parsed.unwrap(), term, "{}", printed);
+    }
+
+    #[test]
+    fn print_fails_or_roundtrips(term in arb_term()) {
+        if let Ok(printed) = term.to_source() {
+            let parsed = parse_term(&printed);
+            prop_assert!(parsed.is_ok(), "{printed}: {}", parsed.unwrap_err());
+            prop_assert_eq!(parsed.unwrap(), term, "{}", printed);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use crate::{PrimType, Term, Type};
 
-use crate::language::term::Primitive;
+use crate::language::term::{Float, Primitive};
 
 pub trait ToTerm {
     fn to_term(self) -> Term;
@@ -28,12 +28,18 @@ impl ToTerm for Term {
     }
 }
 
-impl ToTerm for u64 {
+impl ToTerm for i64 {
     fn to_term(self) -> Term {
         Term::Prim(Primitive::Long(self))
     }
 }
 
+impl ToTerm for f64 {
+    fn to_term(self) -> Term {
+        Term::Prim(Primitive::Float(Float(self)))
+    }
+}
+
 impl ToTerm for String {
     fn to_term(self) -> Term {
         Term::Prim(Primitive::Text(self))
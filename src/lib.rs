@@ -1,11 +1,22 @@
 mod data;
 
+pub mod diagnostics;
 pub mod language;
-mod parse;
+pub mod parse;
+pub mod format;
+pub mod repl;
+pub mod export;
+pub mod import;
+pub mod canonical;
+pub mod cbor;
+pub mod loader;
+pub mod de;
+pub mod ser;
 pub mod evaltime;
 pub mod plugins;
 pub mod fp;
 
 pub use data::*;
 pub use language::term::*;
+pub use de::{from_str, from_value};
 
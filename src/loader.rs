@@ -0,0 +1,419 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use thiserror::Error;
+
+use crate::{
+    canonical,
+    diagnostics::Diagnostic,
+    evaltime::{
+        checking::TypeChecking,
+        evaluate::{Evaluation, Imports},
+        values::Value,
+    },
+    import::from_json,
+    language::source::{NodeId, Sources, Span, Spans},
+    parse::{parse_entry, Entry},
+    Digest, Term,
+};
+
+/// Step of loading a file which failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// The file can not be read
+    Read,
+    Syntax,
+    /// The file imports itself, directly or through other files
+    Cycle,
+    /// The hash of an imported file differs from the one pinned by the import
+    Integrity,
+    Type,
+    Eval,
+}
+
+/// Failure of loading a file or one of the files it imports,
+/// the diagnostic points to every `import` leading to the failed file
+#[derive(Error, Debug, Clone)]
+#[error("{}", .diagnostic.message)]
+pub struct LoadError {
+    pub stage: Stage,
+    pub diagnostic: Diagnostic,
+}
+
+/// Checked and evaluated file
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub term: Arc<Term>,
+    pub typ: Value<()>,
+    pub value: Value<()>,
+    /// Hash of the normal form of the term, covering the imported files
+    pub hash: Digest,
+}
+
+/// Loads kers files along with the files they import
+///
+/// Imported paths are relative to the directory of the importing file.
+/// Every file is loaded once, later imports of the same canonical path reuse the module.
+/// Files with the `.json` extension are imported as described in [`crate::import::from_json`].
+#[derive(Default)]
+pub struct Loader {
+    sources: Sources,
+    /// Spans of the loaded files, they keep the terms alive so the node ids stay valid
+    spans: Vec<Spans>,
+    modules: HashMap<PathBuf, Module>,
+    /// Canonical paths of the files being loaded, each one imported by the previous one
+    loading: Vec<PathBuf>,
+    imports: Arc<Imports<()>>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Loader::default()
+    }
+
+    /// Sources of all the loaded files, for rendering the diagnostics
+    pub fn sources(&self) -> &Sources {
+        &self.sources
+    }
+
+    /// Loads the file at `path` and the files it imports
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<Module, LoadError> {
+        self.import(path.as_ref(), None)
+    }
+
+    /// Loads the `input`, `path` is the file it was read from, imports are resolved against the directory of it,
+    /// or against the working directory when there is no file.
+    /// The input may differ from the contents of the file, so it is not cached as the module of the file.
+    pub fn load_source(
+        &mut self,
+        name: impl Into<String>,
+        input: &str,
+        path: Option<&Path>,
+    ) -> Result<Module, LoadError> {
+        let name = name.into();
+        let Some(path) = path else {
+            return self.module(name, input, Path::new(""));
+        };
+        self.loading.push(canonical(path, None)?);
+        let module = self.module(name, input, path.parent().unwrap_or(Path::new("")));
+        self.loading.pop();
+        module
+    }
+
+    /// Loads the imported file, `site` is the location of the `import` term
+    fn import(&mut self, path: &Path, site: Option<Span>) -> Result<Module, LoadError> {
+        let name = path.display().to_string();
+        let canonical = canonical(path, site)?;
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok(module.clone());
+        }
+        if let Some(start) = self.loading.iter().position(|file| *file == canonical) {
+            let chain: Vec<_> = self.loading[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|file| file.display().to_string())
+                .collect();
+            let diagnostic = Diagnostic::error(format!("import cycle: {}", chain.join(" -> ")));
+            let diagnostic = match site {
+                Some(site) => {
+                    let loaded = canonical.display();
+                    diagnostic.with_label(site, format!("{loaded} is being loaded"))
+                }
+                None => diagnostic,
+            };
+            return Err(LoadError {
+                stage: Stage::Cycle,
+                diagnostic,
+            });
+        }
+        let text = fs::read_to_string(path).map_err(|err| {
+            failed(
+                Stage::Read,
+                Diagnostic::error(format!("can not read {name}")).with_note(err.to_string()),
+                site,
+            )
+        })?;
+        self.loading.push(canonical.clone());
+        let module = if path.extension().is_some_and(|ext| ext == "json") {
+            self.json(&name, &text)
+        } else {
+            self.module(name, &text, path.parent().unwrap_or(Path::new("")))
+        };
+        self.loading.pop();
+        let module = module.map_err(|mut err| {
+            if let Some(site) = site {
+                err.diagnostic = err.diagnostic.with_label(site, "imported here");
+            }
+            err
+        })?;
+        self.modules.insert(canonical, module.clone());
+        Ok(module)
+    }
+
+    /// Parses, checks and evaluates the kers source, after loading the files it imports
+    fn module(&mut self, name: String, input: &str, dir: &Path) -> Result<Module, LoadError> {
+        let file = self.sources.add(name, input);
+        let (term, spans) = parse_entry(input, file, Entry::Term)
+            .map_err(|err| failed(Stage::Syntax, Diagnostic::syntax(&err), None))?;
+        let mut imports = vec![];
+        collect_imports(&term, &spans, &mut imports);
+        self.spans.push(spans);
+        let mut hashes = HashMap::new();
+        for (node, path, pin, site) in imports {
+            let module = self.import(&dir.join(&path), site)?;
+            if let Some(pin) = pin.filter(|pin| *pin != module.hash) {
+                let diagnostic = Diagnostic::error(format!("integrity check failed for {path}"))
+                    .with_note(format!("expected {pin}"))
+                    .with_note(format!("found {}", module.hash));
+                let diagnostic = match site {
+                    Some(site) => diagnostic.with_label(site, "the imported file has changed"),
+                    None => diagnostic,
+                };
+                return Err(LoadError {
+                    stage: Stage::Integrity,
+                    diagnostic,
+                });
+            }
+            hashes.insert(node, module.hash);
+            Arc::make_mut(&mut self.imports).insert(node, module.typ, module.value);
+        }
+        let hash = canonical::digest(&term, |import| hashes.get(&NodeId::of(import)).copied());
+        self.checked(term, hash)
+    }
+
+    fn json(&mut self, name: &str, input: &str) -> Result<Module, LoadError> {
+        let term = from_json(input).map_err(|err| {
+            failed(
+                Stage::Syntax,
+                Diagnostic::error(format!("can not import {name}: {err}")),
+                None,
+            )
+        })?;
+        let hash = canonical::digest(&term, |_| None);
+        self.checked(term, hash)
+    }
+
+    fn checked(&mut self, term: Arc<Term>, hash: Digest) -> Result<Module, LoadError> {
+        let mut checking = TypeChecking::new(()).with_imports(self.imports.clone());
+        let typ = checking
+            .check(&term, &Value::record_type(vec![]))
+            .map_err(|err| {
+                let span = self.span(checking.failed_at());
+                failed(Stage::Type, Diagnostic::typing(&err, span), None)
+            })?;
+        let mut evaluation = Evaluation::new(()).with_imports(self.imports.clone());
+        let value = evaluation.eval(&term, &Value::empty()).map_err(|err| {
+            let span = self.span(evaluation.failed_at());
+            failed(Stage::Eval, Diagnostic::evaluation(&err, span), None)
+        })?;
+        Ok(Module {
+            term,
+            typ,
+            value,
+            hash,
+        })
+    }
+
+    /// Location of the node in any of the loaded files
+    fn span(&self, node: Option<NodeId>) -> Option<Span> {
+        let node = node?;
+        self.spans.iter().find_map(|spans| spans.get_node(node))
+    }
+}
+
+fn canonical(path: &Path, site: Option<Span>) -> Result<PathBuf, LoadError> {
+    fs::canonicalize(path).map_err(|err| {
+        let name = path.display();
+        let diagnostic = Diagnostic::error(format!("can not read {name}"));
+        failed(Stage::Read, diagnostic.with_note(err.to_string()), site)
+    })
+}
+
+type Import = (NodeId, String, Option<Digest>, Option<Span>);
+
+fn failed(stage: Stage, diagnostic: Diagnostic, site: Option<Span>) -> LoadError {
+    let diagnostic = match site {
+        Some(site) => diagnostic.with_label(site, "imported here"),
+        None => diagnostic,
+    };
+    LoadError { stage, diagnostic }
+}
+
+/// `import` terms of the tree, with their ids, paths, pinned hashes and locations
+fn collect_imports(term: &Term, spans: &Spans, imports: &mut Vec<Import>) {
+    if let Term::Import { path, hash } = term {
+        let pin = hash.as_deref().copied();
+        imports.push((NodeId::of(term), path.clone(), pin, spans.get(term)));
+    }
+    for child in term.children() {
+        collect_imports(child, spans, imports);
+    }
+}
+
+#[cfg(test)]
+use crate::diagnostics::Style;
+
+/// Directory with the files for a test, removed beforehand
+#[cfg(test)]
+fn files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kers-{test}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for (name, text) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn load_imports() {
+    let dir = files(
+        "load-imports",
+        &[
+            (
+                "main.kers",
+                "let base = import 'lib/base.kers' in (a = (host = base.host, port = 81), b = base, data = import 'data.json')",
+            ),
+            ("lib/base.kers", "(host = import 'host.kers', port = 80)"),
+            ("lib/host.kers", "let name = 'h' in \"${name}.example\""),
+            ("data.json", r#"{"ids": [1, 2]}"#),
+            ("lib/extra.kers", "import 'base.kers'"),
+        ],
+    );
+    let mut loader = Loader::new();
+    let module = loader.load(dir.join("main.kers")).unwrap();
+    assert_eq!(
+        module.value.to_string(),
+        "(a = (host = 'h.example', port = 81), b = (host = 'h.example', port = 80), data = (ids = (1, 2)))"
+    );
+    assert_eq!(
+        module.typ.to_string(),
+        "{a: {host: #text, port: #int}, b: {host: #text, port: #int}, data: {ids: {#int, #int}}}"
+    );
+    // the files are loaded once, by their canonical paths
+    let base = loader.load(dir.join("lib/../lib/base.kers")).unwrap();
+    let again = loader.load(dir.join("lib/base.kers")).unwrap();
+    assert!(Arc::ptr_eq(&base.term, &again.term));
+    let extra = dir.join("lib/extra.kers");
+    let again = loader
+        .load_source("extra", "import 'base.kers'", Some(&extra))
+        .unwrap();
+    let stdin = loader.load_source("<stdin>", "import 'base.kers'", None);
+    assert_eq!(stdin.unwrap_err().stage, Stage::Read);
+    assert_eq!(again.value, base.value);
+    // the given input is not taken for the contents of the file
+    let edited = loader.load_source("extra", "1", Some(&extra)).unwrap();
+    assert_eq!(edited.value.to_string(), "1");
+    assert_eq!(loader.load(&extra).unwrap().value, base.value);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn load_cycle() {
+    let dir = files(
+        "load-cycle",
+        &[
+            ("a.kers", "(b = import 'b.kers')"),
+            ("b.kers", "import './a.kers'"),
+        ],
+    );
+    let err = Loader::new().load(dir.join("a.kers")).unwrap_err();
+    assert_eq!(err.stage, Stage::Cycle);
+    let dir = fs::canonicalize(dir).unwrap();
+    let a = dir.join("a.kers").display().to_string();
+    let b = dir.join("b.kers").display().to_string();
+    assert_eq!(err.to_string(), format!("import cycle: {a} -> {b} -> {a}"));
+    assert_eq!(err.diagnostic.labels.len(), 2);
+    assert_eq!(
+        err.diagnostic.labels[0].message,
+        format!("{a} is being loaded")
+    );
+    // the file given with its contents is a part of the cycle
+    let a_path = dir.join("a.kers");
+    let err = Loader::new()
+        .load_source("a", "import 'b.kers'", Some(&a_path))
+        .unwrap_err();
+    assert_eq!(err.to_string(), format!("import cycle: {a} -> {b} -> {a}"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn load_errors() {
+    let dir = files(
+        "load-errors",
+        &[
+            ("main.kers", "(x = import 'bad.kers')"),
+            ("bad.kers", "(\n  y = 1 'a'\n)"),
+            ("missing.kers", "(a = 1, b = import 'nothing.kers')"),
+        ],
+    );
+    let mut loader = Loader::new();
+    let err = loader.load(dir.join("main.kers")).unwrap_err();
+    assert_eq!(err.stage, Stage::Type);
+    let rendered = err.diagnostic.render(loader.sources(), Style::Plain);
+    let expected = format!(
+        "\
+error: expected a function
+ --> {bad}:2:7
+  |
+2 |   y = 1 'a'
+  |       ^ this has type #int
+ --> {main}:1:6
+  |
+1 | (x = import 'bad.kers')
+  |      ----------------- imported here
+",
+        bad = dir.join("bad.kers").display(),
+        main = dir.join("main.kers").display(),
+    );
+    assert_eq!(rendered, expected);
+    let err = loader.load(dir.join("missing.kers")).unwrap_err();
+    assert_eq!(err.stage, Stage::Read);
+    assert_eq!(err.diagnostic.labels[0].message, "imported here");
+    let err = loader
+        .load_source("<input>", "import 'x", None)
+        .unwrap_err();
+    assert_eq!(err.stage, Stage::Syntax);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn load_pinned() {
+    let dir = files(
+        "load-pinned",
+        &[
+            ("lib.kers", "(port = import 'port.kers')"),
+            ("port.kers", "80"),
+            (
+                "layout.kers",
+                "(\n  // the port\n  'port' = import \"port.kers\",\n)",
+            ),
+        ],
+    );
+    let lib = Loader::new().load(dir.join("lib.kers")).unwrap();
+    let layout = Loader::new().load(dir.join("layout.kers")).unwrap();
+    assert_eq!(lib.hash, layout.hash);
+    let main = format!("(lib = import 'lib.kers' {})", lib.hash);
+    fs::write(dir.join("main.kers"), &main).unwrap();
+    let module = Loader::new().load(dir.join("main.kers")).unwrap();
+    assert_eq!(module.value.to_string(), "(lib = (port = 80))");
+    // the hash covers the files imported by the pinned one
+    fs::write(dir.join("port.kers"), "81").unwrap();
+    let mut loader = Loader::new();
+    let err = loader.load(dir.join("main.kers")).unwrap_err();
+    assert_eq!(err.stage, Stage::Integrity);
+    assert_eq!(err.to_string(), "integrity check failed for lib.kers");
+    let changed = Loader::new().load(dir.join("lib.kers")).unwrap().hash;
+    assert_eq!(
+        err.diagnostic.notes,
+        [format!("expected {}", lib.hash), format!("found {changed}")]
+    );
+    let rendered = err.diagnostic.render(loader.sources(), Style::Plain);
+    assert!(rendered.contains("^^^^ the imported file has changed"));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -1,3 +1,353 @@
-fn main() {
-    println!("Hello, world!");
+use std::{
+    fs,
+    io::{self, BufRead, IsTerminal, Read, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+use kers::{
+    diagnostics::{Diagnostic, Style},
+    evaltime::values::Value,
+    export::{self, ExportError, Format},
+    format::{format_named, FormatOptions},
+    language::source::Sources,
+    loader::{LoadError, Loader, Stage},
+    parse::{parse_named, Entry, ParseError},
+    repl::{Repl, Reply},
+};
+
+const USAGE: &str = "\
+usage: kers <command> [options] [files...]
+
+Reads the standard input when no files are given, or for the file `-`.
+Imports are resolved against the directory of the importing file,
+or against the working directory for the standard input.
+
+commands:
+  eval [--format F] [files...]
+                             print the evaluated value, F is kers (default), json, yaml or toml
+  check [files...]           type-check and report the errors
+  type [files...]            print the inferred type
+  parse [files...]           print the syntax tree
+  hash [files...]            print the hash to pin the file in `import 'file' sha256:...`
+  repl                       interactive session, :help lists its commands
+  fmt [--check] [files...]   rewrite files in the canonical layout, stdin to stdout
+                             with --check only report the files which are not formatted
+
+exit codes:
+  0 success, 1 unformatted files, 2 usage or input errors, import cycles,
+  3 syntax errors, 4 type errors, 5 evaluation errors,
+  6 values which can not be written in the requested format,
+  7 changed files imported with a hash,
+  the highest one when several inputs fail
+";
+
+/// `--check` found files which are not formatted
+const EXIT_UNFORMATTED: u8 = 1;
+/// Unknown command or option, unreadable file or output, import cycle
+const EXIT_USAGE: u8 = 2;
+const EXIT_SYNTAX: u8 = 3;
+const EXIT_TYPE: u8 = 4;
+const EXIT_EVAL: u8 = 5;
+/// The value has no representation in the requested format
+const EXIT_EXPORT: u8 = 6;
+/// An imported file does not match the hash pinning it
+const EXIT_INTEGRITY: u8 = 7;
+
+/// Representation of the evaluated values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Kers,
+    Export(Format),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Eval,
+    Check,
+    Type,
+    Parse,
+    Hash,
+}
+
+/// Error reported for one of the inputs
+struct Failure {
+    code: u8,
+    diagnostic: Diagnostic,
+    sources: Sources,
+}
+
+impl From<ParseError> for Failure {
+    fn from(err: ParseError) -> Self {
+        let (code, sources) = match &err {
+            ParseError::Io { .. } => (EXIT_USAGE, Sources::default()),
+            ParseError::Syntax { sources, .. } => (EXIT_SYNTAX, sources.clone()),
+        };
+        Failure {
+            code,
+            diagnostic: err.diagnostic(),
+            sources,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match args.first().map(String::as_str) {
+        Some("eval") => Command::Eval,
+        Some("check") => Command::Check,
+        Some("type") => Command::Type,
+        Some("parse") => Command::Parse,
+        Some("hash") => Command::Hash,
+        Some("fmt") => return fmt(&args[1..]),
+        Some("repl") => return repl(),
+        Some("help" | "--help" | "-h") => {
+            let written = io::stdout().lock().write_all(USAGE.as_bytes());
+            return written.map_or_else(|err| write_error(err, 0), |()| ExitCode::SUCCESS);
+        }
+        Some(other) => return usage_error(&format!("unknown command {other}")),
+        None => return usage_error("missing command"),
+    };
+    let known: &[&str] = match command {
+        Command::Eval => &["--format="],
+        _ => &[],
+    };
+    let (options, files) = match arguments(&args[1..], known) {
+        Ok(arguments) => arguments,
+        Err(code) => return code,
+    };
+    let mut output = Output::Kers;
+    for (_, value) in options {
+        output = match value {
+            "kers" => Output::Kers,
+            "json" => Output::Export(Format::Json),
+            "yaml" => Output::Export(Format::Yaml),
+            "toml" => Output::Export(Format::Toml),
+            other => return usage_error(&format!("unknown format {other}")),
+        };
+    }
+    let mut status = 0;
+    let mut loader = Loader::new();
+    let mut stdout = io::stdout().lock();
+    for file in files {
+        let result = read_input(file).and_then(|(name, input)| {
+            let path = (file != "-").then(|| Path::new(file));
+            run(&mut loader, command, output, (&name, &input, path))
+        });
+        match result {
+            Ok(output) => {
+                if let Err(err) = writeln!(stdout, "{}", output.trim_end_matches('\n')) {
+                    return write_error(err, status);
+                }
+            }
+            Err(failure) => {
+                report(&failure);
+                status = status.max(failure.code);
+            }
+        }
+    }
+    ExitCode::from(status)
+}
+
+/// Output of the command for a single input, given with its name and the file it was read from
+fn run(
+    loader: &mut Loader,
+    command: Command,
+    output: Output,
+    (name, input, path): (&str, &str, Option<&Path>),
+) -> Result<String, Failure> {
+    if command == Command::Parse {
+        let parsed = parse_named(name, input, Entry::Term)?;
+        return Ok(format!("{:#?}", parsed.term));
+    }
+    let module = match loader.load_source(name, input, path) {
+        Ok(module) => module,
+        Err(err) => return Err(load_failure(err, loader.sources())),
+    };
+    match command {
+        Command::Check => Ok(format!("{name}: ok")),
+        Command::Type => Ok(module.typ.to_string()),
+        Command::Hash => Ok(module.hash.to_string()),
+        _ => export(&module.value, output).map_err(|err| Failure {
+            code: EXIT_EXPORT,
+            diagnostic: Diagnostic::error(err.to_string()),
+            sources: Sources::default(),
+        }),
+    }
+}
+
+fn load_failure(err: LoadError, sources: &Sources) -> Failure {
+    let code = match err.stage {
+        Stage::Read | Stage::Cycle => EXIT_USAGE,
+        Stage::Integrity => EXIT_INTEGRITY,
+        Stage::Syntax => EXIT_SYNTAX,
+        Stage::Type => EXIT_TYPE,
+        Stage::Eval => EXIT_EVAL,
+    };
+    Failure {
+        code,
+        diagnostic: err.diagnostic,
+        sources: sources.clone(),
+    }
+}
+
+fn export(value: &Value<()>, output: Output) -> Result<String, ExportError> {
+    match output {
+        Output::Kers => Ok(value.to_string()),
+        Output::Export(format) => export::export(value, format),
+    }
+}
+
+fn fmt(args: &[String]) -> ExitCode {
+    let (options, files) = match arguments(args, &["--check"]) {
+        Ok(arguments) => arguments,
+        Err(code) => return code,
+    };
+    let check = !options.is_empty();
+    let options = FormatOptions::default();
+    let mut status = 0;
+    let mut stdout = io::stdout().lock();
+    for file in files {
+        let formatted = read_input(file).and_then(|(name, input)| {
+            let formatted = format_named(name.clone(), &input, &options)?;
+            Ok((name, input, formatted))
+        });
+        let (name, input, formatted) = match formatted {
+            Ok(formatted) => formatted,
+            Err(failure) => {
+                report(&failure);
+                status = status.max(failure.code);
+                continue;
+            }
+        };
+        if check {
+            if formatted != input {
+                status = status.max(EXIT_UNFORMATTED);
+                if let Err(err) = writeln!(stdout, "{name} is not formatted") {
+                    return write_error(err, status);
+                }
+            }
+        } else if file == "-" {
+            if let Err(err) = stdout.write_all(formatted.as_bytes()) {
+                return write_error(err, status);
+            }
+        } else if formatted != input {
+            if let Err(source) = fs::write(file, formatted) {
+                report(&ParseError::Io { name, source }.into());
+                status = status.max(EXIT_USAGE);
+            }
+        }
+    }
+    ExitCode::from(status)
+}
+
+fn repl() -> ExitCode {
+    session(&mut io::stdout().lock()).unwrap_or_else(|err| write_error(err, 0))
+}
+
+fn session(stdout: &mut impl Write) -> io::Result<ExitCode> {
+    let mut repl = Repl::new(style());
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        write!(stdout, "{}", repl.prompt())?;
+        stdout.flush()?;
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(err)) => {
+                eprintln!("can not read the input: {err}");
+                return Ok(ExitCode::from(EXIT_USAGE));
+            }
+            None => {
+                writeln!(stdout)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+        };
+        match repl.feed(&line) {
+            Reply::More => {}
+            Reply::Output(output) if output.is_empty() => {}
+            Reply::Output(output) => writeln!(stdout, "{output}")?,
+            Reply::Error(err) => eprint!("{err}"),
+            Reply::Quit => return Ok(ExitCode::SUCCESS),
+        }
+    }
+}
+
+/// Options with their values and files given to the command
+type Arguments<'a> = (Vec<(&'a str, &'a str)>, Vec<&'a str>);
+
+/// Parses the arguments of the command
+///
+/// `known` are the options the command accepts, the ones ending with `=` take a value,
+/// given either as `--name=value` or as `--name value`.
+fn arguments<'a>(args: &'a [String], known: &[&str]) -> Result<Arguments<'a>, ExitCode> {
+    let mut options = vec![];
+    let mut files = vec![];
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            files.push(arg);
+            continue;
+        }
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None),
+        };
+        if known.contains(&name) && value.is_none() {
+            options.push((name, ""));
+        } else if known.iter().any(|opt| opt.strip_suffix('=') == Some(name)) {
+            match value.or_else(|| args.next()) {
+                Some(value) => options.push((name, value)),
+                None => return Err(usage_error(&format!("missing value of {name}"))),
+            }
+        } else {
+            return Err(usage_error(&format!("unknown option {arg}")));
+        }
+    }
+    if files.is_empty() {
+        files.push("-");
+    }
+    Ok((options, files))
+}
+
+/// A closed standard output, as in `kers parse file | head`, quietly ends the command
+fn write_error(err: io::Error, status: u8) -> ExitCode {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        return ExitCode::from(status);
+    }
+    eprintln!("can not write the output: {err}");
+    ExitCode::from(status.max(EXIT_USAGE))
+}
+
+fn usage_error(msg: &str) -> ExitCode {
+    eprintln!("{msg}");
+    eprint!("{USAGE}");
+    ExitCode::from(EXIT_USAGE)
+}
+
+/// Contents of the file, `-` stands for the standard input
+fn read_input(file: &str) -> Result<(String, String), Failure> {
+    let name = if file == "-" { "<stdin>" } else { file }.to_string();
+    let mut text = String::new();
+    let read = if file == "-" {
+        io::stdin().read_to_string(&mut text).map(|_| ())
+    } else {
+        fs::read_to_string(file).map(|contents| text = contents)
+    };
+    match read {
+        Ok(()) => Ok((name, text)),
+        Err(source) => Err(ParseError::Io { name, source }.into()),
+    }
+}
+
+fn report(failure: &Failure) {
+    eprint!("{}", failure.diagnostic.render(&failure.sources, style()));
+}
+
+/// Colored diagnostics when they are shown on a terminal
+fn style() -> Style {
+    if io::stderr().is_terminal() {
+        Style::Ansi
+    } else {
+        Style::Plain
+    }
 }
@@ -1,30 +1,178 @@
-use core::panic;
-use std::{fmt::Display, sync::Arc};
+use std::{fs, io, path::Path, sync::Arc};
 
 use pest::Parser;
 use pest_derive::Parser;
+use thiserror::Error;
 
-use crate::Term;
+use crate::{
+    diagnostics::{Diagnostic, Style},
+    language::source::{FileId, Sources, Spans},
+    Term,
+};
 
 mod decode;
 mod error;
-pub use error::SyntaxError;
+pub use error::{NumberError, SyntaxError};
 
 #[derive(Parser)]
 #[grammar = "kers.pest"]
-pub struct Kers;
+pub(crate) struct Kers;
+
+/// Syntactic form to parse the whole input as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry {
+    /// Any term
+    Term,
+    /// A term which is a type: a primitive type, a record or tuple type, a function type or an intersection
+    Type,
+    /// `(name = value, ...)` or `()`
+    Record,
+    /// `{name: type, ...}` or `{}`
+    RecordType,
+    /// `(value, ...)`
+    Tuple,
+    /// `{type, ...}`
+    TupleType,
+}
+
+/// Term parsed from a named source, along with everything needed to report diagnostics about it
+#[derive(Debug, Clone)]
+pub struct Parsed {
+    pub term: Arc<Term>,
+    pub spans: Spans,
+    pub file: FileId,
+    pub sources: Sources,
+}
+
+#[derive(Error, Debug)]
+pub enum ParseError {
+    #[error("can not read {name}: {source}")]
+    Io { name: String, source: io::Error },
+    #[error("{name}:{error}")]
+    Syntax {
+        name: String,
+        error: SyntaxError,
+        sources: Sources,
+    },
+}
+
+impl ParseError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            ParseError::Io { name, source } => {
+                Diagnostic::error(format!("can not read {name}")).with_note(source.to_string())
+            }
+            ParseError::Syntax { error, .. } => Diagnostic::syntax(error),
+        }
+    }
+
+    /// Report of the error with the offending source snippet
+    pub fn render(&self, style: Style) -> String {
+        match self {
+            ParseError::Io { .. } => self.diagnostic().render(&Sources::default(), style),
+            ParseError::Syntax { sources, .. } => self.diagnostic().render(sources, style),
+        }
+    }
+}
+
+/// Parses a term from the string
+pub fn parse_str(input: &str) -> Result<Parsed, ParseError> {
+    parse_named("<string>", input, Entry::Term)
+}
+
+/// Parses a term from the file at `path`
+pub fn parse_file(path: impl AsRef<Path>) -> Result<Parsed, ParseError> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    match fs::read_to_string(path) {
+        Ok(text) => parse_named(name, &text, Entry::Term),
+        Err(source) => Err(ParseError::Io { name, source }),
+    }
+}
+
+/// Parses a term from the whole contents of the `reader`, `name` is used in diagnostics
+pub fn parse_reader(
+    name: impl Into<String>,
+    mut reader: impl io::Read,
+) -> Result<Parsed, ParseError> {
+    let name = name.into();
+    let mut text = String::new();
+    match reader.read_to_string(&mut text) {
+        Ok(_) => parse_named(name, &text, Entry::Term),
+        Err(source) => Err(ParseError::Io { name, source }),
+    }
+}
+
+/// Parses the whole `input` as the given syntactic form, `name` is used in diagnostics
+pub fn parse_named(
+    name: impl Into<String>,
+    input: &str,
+    entry: Entry,
+) -> Result<Parsed, ParseError> {
+    let name = name.into();
+    let mut sources = Sources::default();
+    let file = sources.add(name.clone(), input);
+    match parse_entry(input, file, entry) {
+        Ok((term, spans)) => Ok(Parsed {
+            term,
+            spans,
+            file,
+            sources,
+        }),
+        Err(error) => Err(ParseError::Syntax {
+            name,
+            error,
+            sources,
+        }),
+    }
+}
 
 #[allow(unused)]
-fn parse_term(input: &str) -> Result<Arc<Term>, SyntaxError> {
-    let mut top = Kers::parse(Rule::term, input)?;
-    decode::term(top.read(Rule::term)?)
+pub(crate) fn parse_term(input: &str) -> Result<Arc<Term>, SyntaxError> {
+    parse_source(input, FileId::default()).map(|(term, _)| term)
 }
 
-trait UnwrapDisplay {
+/// Parses the whole `input` remembering source locations of the produced terms
+pub(crate) fn parse_source(input: &str, file: FileId) -> Result<(Arc<Term>, Spans), SyntaxError> {
+    parse_entry(input, file, Entry::Term)
+}
+
+pub(crate) fn parse_entry(
+    input: &str,
+    file: FileId,
+    entry: Entry,
+) -> Result<(Arc<Term>, Spans), SyntaxError> {
+    let rule = match entry {
+        Entry::Term | Entry::Type => Rule::file,
+        Entry::Record => Rule::record_entry,
+        Entry::RecordType => Rule::record_type_entry,
+        Entry::Tuple => Rule::tuple_entry,
+        Entry::TupleType => Rule::tuple_type_entry,
+    };
+    let mut top = Kers::parse(rule, input).map_err(|err| SyntaxError::parse_error(err, file))?;
+    let Some(pair) = top.next() else {
+        unreachable!("pest returns the parsed rule on success")
+    };
+    let (term, spans) = decode::Decoder::new(file).entry(pair)?;
+    if entry == Entry::Type && !matches!(*term, Term::Type(_)) {
+        return Err(SyntaxError::Other {
+            msg: "expected a type".to_string(),
+            span: spans.get(&term).expect("parsed terms have spans"),
+        });
+    }
+    Ok((term, spans))
+}
+
+#[cfg(test)]
+use std::fmt::Display;
+
+#[cfg(test)]
+pub(crate) trait UnwrapDisplay {
     type Output;
     fn unwrap_print(self) -> Self::Output;
 }
 
+#[cfg(test)]
 impl<A, E: Display> UnwrapDisplay for Result<A, E> {
     type Output = A;
     fn unwrap_print(self) -> Self::Output {
@@ -36,7 +184,7 @@ impl<A, E: Display> UnwrapDisplay for Result<A, E> {
 }
 
 #[cfg(test)]
-use crate::{AsTyp, PrimType, ToTerm};
+use crate::{AsTyp, Digest, Key, PrimType, ToTerm, Type};
 
 #[test]
 fn check_various_simple_stuff() {
@@ -63,7 +211,7 @@ fn check_object() {
         [
             ("greet", "Hello".to_term()),
             ("target", "World".to_term()),
-            ("my \"agy\"", 38u64.to_term()),
+            ("my \"agy\"", 38i64.to_term()),
             ("xxx", Term::get("xxx"))
         ]
         .to_arc_term()
@@ -77,7 +225,6 @@ fn check_empty_object() {
     assert_eq!(res, Term::Empty.to_arc_term())
 }
 
-use self::decode::PairsExt;
 #[test]
 fn check_record_type() {
     let input = "{greet: str, 'target': str, 'my \"agy\"': int, xxx: xxx}";
@@ -108,3 +255,574 @@ fn check_builtin_types() {
     let int_res = parse_term("#int").unwrap_print();
     assert_eq!(int_res, PrimType::Long.to_arc_term());
 }
+
+#[test]
+fn check_function_type_is_right_associative() {
+    let res = parse_term("a -> b -> c").unwrap_print();
+    let function = |dom, codom| Type::Function { dom, codom }.to_arc_term();
+    let expected = function(
+        Term::get("a").to_arc_term(),
+        function(Term::get("b").to_arc_term(), Term::get("c").to_arc_term()),
+    );
+    assert_eq!(res, expected);
+}
+
+#[test]
+fn check_lambda() {
+    let res = parse_term("\\{x: #int} => (y = x)").unwrap_print();
+    assert_eq!(
+        res,
+        Term::Lambda {
+            dom: AsTyp([("x", PrimType::Long.to_term())]).to_arc_term(),
+            body: [("y", Term::get("x"))].to_arc_term(),
+        }
+        .to_arc_term()
+    );
+}
+
+#[test]
+fn check_lambda_body_extends_to_the_right() {
+    let res = parse_term("\\point => \\{} => x -> y").unwrap_print();
+    let function = Type::Function {
+        dom: Term::get("x").to_arc_term(),
+        codom: Term::get("y").to_arc_term(),
+    };
+    let inner = Term::Lambda {
+        dom: PrimType::Any.to_arc_term(),
+        body: function.to_arc_term(),
+    };
+    let expected = Term::Lambda {
+        dom: Term::get("point").to_arc_term(),
+        body: inner.to_arc_term(),
+    };
+    assert_eq!(res, expected.to_arc_term());
+}
+
+#[test]
+fn check_lambda_in_record() {
+    let res = parse_term("(f = \\t => @@, g = f)").unwrap_print();
+    let lambda = Term::Lambda {
+        dom: Term::get("t").to_arc_term(),
+        body: Term::Reflect.to_arc_term(),
+    };
+    assert_eq!(res, [("f", lambda), ("g", Term::get("f"))].to_arc_term());
+}
+
+#[test]
+fn check_lambda_application() {
+    let res = parse_term("(\\t => x) (x = 1)").unwrap_print();
+    let lambda = Term::Lambda {
+        dom: Term::get("t").to_arc_term(),
+        body: Term::get("x").to_arc_term(),
+    };
+    let expected = Term::apply(lambda.to_arc_term(), [("x", 1i64)].to_arc_term());
+    assert_eq!(res, expected.to_arc_term());
+}
+
+#[test]
+fn check_parenthesized_term() {
+    let res = parse_term("(x)").unwrap_print();
+    assert_eq!(res, Term::get("x").to_arc_term());
+}
+
+#[cfg(test)]
+fn positional<const N: usize>(elems: [Term; N]) -> [Term; N] {
+    let mut idx = 0;
+    elems.map(|value| {
+        let name = Key::Index(idx);
+        idx += 1;
+        Term::Set {
+            name,
+            value: value.to_arc_term(),
+        }
+    })
+}
+
+#[test]
+fn check_tuple() {
+    let res = parse_term("(1, 'two', (x = 3))").unwrap_print();
+    let expected = positional([1i64.to_term(), "two".to_term(), [("x", 3i64)].to_term()]);
+    assert_eq!(res, expected.to_arc_term());
+
+    let res = parse_term("(x;)").unwrap_print();
+    assert_eq!(res, positional([Term::get("x")]).to_arc_term());
+}
+
+#[test]
+fn check_tuple_type() {
+    let res = parse_term("{#int, #text,}").unwrap_print();
+    let field = |idx, typ: PrimType| Type::Field {
+        name: Key::Index(idx),
+        typ: typ.to_arc_term(),
+    };
+    let expected = Type::And {
+        left: field(0, PrimType::Long).to_arc_term(),
+        right: field(1, PrimType::Text).to_arc_term(),
+    };
+    assert_eq!(res, expected.to_arc_term());
+}
+
+#[test]
+fn check_positional_access() {
+    let res = parse_term("x.0.12").unwrap_print();
+    let then = |first, next| Term::Then { first, next }.to_arc_term();
+    let expected = then(
+        then(
+            Term::get("x").to_arc_term(),
+            Term::Get(Key::Index(0)).to_arc_term(),
+        ),
+        Term::Get(Key::Index(12)).to_arc_term(),
+    );
+    assert_eq!(res, expected);
+}
+
+#[cfg(test)]
+fn all_nodes(term: &Arc<Term>) -> Vec<Arc<Term>> {
+    let mut nodes = vec![term.clone()];
+    for child in term.children() {
+        nodes.extend(all_nodes(child));
+    }
+    nodes
+}
+
+#[test]
+fn check_spans() {
+    let input = "(greet = 'Hello',\n  target = f (x = world).name)";
+    let (term, spans) = parse_source(input, FileId(3)).unwrap_print();
+    for node in all_nodes(&term) {
+        let span = spans
+            .get(&node)
+            .unwrap_or_else(|| panic!("no span for {node:?}"));
+        assert_eq!(span.file, FileId(3));
+    }
+
+    let whole = spans.get(&term).unwrap();
+    assert_eq!(
+        (whole.start, whole.end, whole.line, whole.col),
+        (0, input.len(), 1, 1)
+    );
+
+    let world = all_nodes(&term)
+        .into_iter()
+        .find(|node| **node == Term::get("world"))
+        .unwrap();
+    let span = spans.get(&world).unwrap();
+    assert_eq!(&input[span.start..span.end], "world");
+    assert_eq!((span.line, span.col), (2, 19));
+}
+
+#[test]
+fn check_syntax_error_spans() {
+    let err = parse_source("(a = 1,\n b = )", FileId(1)).unwrap_err();
+    assert!(matches!(err, SyntaxError::ParseError { .. }));
+    let span = err.span();
+    assert_eq!((span.file, span.line, span.col), (FileId(1), 2, 6));
+
+    let err = parse_source("(a = 1,\n b = 99999999999999999999)", FileId(1)).unwrap_err();
+    assert!(matches!(err, SyntaxError::ParseNumberError { .. }));
+    let span = err.span();
+    assert_eq!((span.line, span.col, span.end - span.start), (2, 6, 20));
+    assert!(err.to_string().starts_with("2:6: "));
+
+    let err = parse_source("'ab\\uD800'", FileId(1)).unwrap_err();
+    assert!(matches!(err, SyntaxError::CharError { .. }));
+    assert_eq!((err.span().line, err.span().col), (1, 4));
+}
+
+#[test]
+fn check_comments() {
+    let input = "
+        // a line comment
+        (a = 1, /* a /* nested */ block comment */ b = 'text // not a comment') // trailing
+        /* trailing block */";
+    let expected = [
+        ("a", 1i64.to_term()),
+        ("b", "text // not a comment".to_term()),
+    ];
+    assert_eq!(parse_term(input).unwrap_print(), expected.to_arc_term());
+
+    let typ = parse_term("{ x: #int, //// not a doc\n y: #text /** not a doc either */ }");
+    let expected = AsTyp([
+        ("x", PrimType::Long.to_term()),
+        ("y", PrimType::Text.to_term()),
+    ]);
+    assert_eq!(typ.unwrap_print(), expected.to_arc_term());
+
+    assert!(parse_term("(a = 1 /* unterminated /* nested */)").is_err());
+}
+
+#[cfg(test)]
+fn entry_docs(input: &str) -> Vec<(String, Option<String>)> {
+    let (term, spans) = parse_source(input, FileId::default()).unwrap_print();
+    all_nodes(&term)
+        .into_iter()
+        .filter_map(|node| match &*node {
+            Term::Set { name, .. } | Term::Type(Type::Field { name, .. }) => {
+                Some((name.to_string(), spans.doc(&node).map(str::to_string)))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn check_doc_comments() {
+    let input = "
+        /// the whole config
+        (
+            /// first line
+            // a plain comment between
+            ///second line
+            a = 1,
+            b = 2, /// stray doc, not followed by an entry
+        )";
+    let docs = entry_docs(input);
+    assert!(docs.contains(&("a".into(), Some("first line\nsecond line".into()))));
+    assert!(docs.contains(&("b".into(), None)));
+
+    let input = "{
+        /**
+         * Port to listen on
+         *
+         * Must be free
+         */
+        port: #int,
+        /** Host */ host: #text
+    }";
+    let docs = entry_docs(input);
+    assert!(docs.contains(&(
+        "port".into(),
+        Some("Port to listen on\n\nMust be free".into())
+    )));
+    assert!(docs.contains(&("host".into(), Some("Host".into()))));
+
+    let (term, spans) = parse_source("(/// doc\n a = 1)", FileId::default()).unwrap_print();
+    let span = spans.get(&term).unwrap();
+    assert_eq!((span.line, span.col), (2, 2));
+}
+
+#[test]
+fn check_numbers() {
+    let cases: [(&str, Term); 14] = [
+        ("0", 0i64.to_term()),
+        ("-0", 0i64.to_term()),
+        ("42", 42i64.to_term()),
+        ("-42", (-42i64).to_term()),
+        ("1_000_000", 1_000_000i64.to_term()),
+        ("0xFF_ff", 0xffffi64.to_term()),
+        ("-0x80", (-128i64).to_term()),
+        ("0o17", 15i64.to_term()),
+        ("0b1010_0101", 0b1010_0101i64.to_term()),
+        ("-9223372036854775808", i64::MIN.to_term()),
+        ("1.5", 1.5f64.to_term()),
+        ("-0.25", (-0.25f64).to_term()),
+        ("1_0.0_1e1_0", 10.01e10f64.to_term()),
+        ("2E-3", 2e-3f64.to_term()),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(
+            parse_term(input).unwrap_print(),
+            expected.to_arc_term(),
+            "{input}"
+        );
+    }
+
+    let res = parse_term("(a = 0, b = (1.5, -2)).b.0").unwrap_print();
+    let record = [
+        ("a", 0i64.to_term()),
+        (
+            "b",
+            positional([1.5f64.to_term(), (-2i64).to_term()]).to_term(),
+        ),
+    ];
+    let index = |idx: usize| Term::Get(Key::Index(idx)).to_arc_term();
+    let expected = Term::Then {
+        first: Term::Then {
+            first: record.to_arc_term(),
+            next: Term::get("b").to_arc_term(),
+        }
+        .to_arc_term(),
+        next: index(0),
+    };
+    assert_eq!(res, expected.to_arc_term());
+    assert_eq!(
+        parse_term("#float").unwrap_print(),
+        PrimType::Float.to_arc_term()
+    );
+}
+
+#[test]
+fn check_invalid_numbers() {
+    for input in ["0x", "12abc", "0b102", "1.", "1e", "-"] {
+        assert!(parse_term(input).is_err(), "{input}");
+    }
+
+    for input in [
+        "9223372036854775808",
+        "0x1_0000_0000_0000_0000",
+        "(x = 1e400)",
+    ] {
+        let err = parse_term(input).unwrap_err();
+        assert!(
+            matches!(err, SyntaxError::ParseNumberError { .. }),
+            "{input}: {err}"
+        );
+    }
+    let err = parse_source("(x =\n  -1e400)", FileId::default()).unwrap_err();
+    let span = err.span();
+    assert_eq!((span.line, span.col, span.end - span.start), (2, 3, 6));
+    assert_eq!(err.to_string(), "2:3: number too large to fit in a float");
+}
+
+#[test]
+fn check_string_escapes() {
+    let cases = [
+        (r"'\u00e9\u0041'", "éA"),
+        (r"'\u{1F600} \u{41}'", "\u{1F600} A"),
+        (r#""\"\'\\\/\b\f\n\r\t""#, "\"'\\/\x08\x0c\n\r\t"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(
+            parse_term(input).unwrap_print(),
+            expected.to_arc_term(),
+            "{input}"
+        );
+    }
+
+    for input in [r"'\uD83D'", r"'\u{DC00}'", r"'\u{110000}'"] {
+        let err = parse_term(input).unwrap_err();
+        assert!(
+            matches!(err, SyntaxError::CharError { .. }),
+            "{input}: {err}"
+        );
+    }
+    for input in [r"'\u{}'", r"'\u{1234567}'", r"'\u12'", r"'\x'"] {
+        assert!(parse_term(input).is_err(), "{input}");
+    }
+}
+
+#[test]
+fn check_raw_strings() {
+    let cases = [
+        (r#"r"C:\dir\n""#, r"C:\dir\n"),
+        (r##"r#"say "hi" \u0041"#"##, r#"say "hi" \u0041"#),
+        (r###"r##"a "# b"##"###, r##"a "# b"##),
+        (r#"r"""#, ""),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(
+            parse_term(input).unwrap_print(),
+            expected.to_arc_term(),
+            "{input}"
+        );
+    }
+    let res = parse_term(r#"(r = r, r"raw key" = 1)"#).unwrap_print();
+    let expected = [("r", Term::get("r")), ("raw key", 1i64.to_term())];
+    assert_eq!(res, expected.to_arc_term());
+    assert!(parse_term(r##"r#"unterminated""##).is_err());
+}
+
+#[test]
+fn check_multiline_strings() {
+    let input = r#"(query = """
+        SELECT *
+          FROM "users"
+
+        WHERE name = '\u{1F600}'\n
+        """)"#;
+    let expected = "SELECT *\n  FROM \"users\"\n\nWHERE name = '\u{1F600}'\n";
+    assert_eq!(
+        parse_term(input).unwrap_print(),
+        [("query", expected)].to_arc_term()
+    );
+
+    let cases = [
+        (r#""""one line""""#, "one line"),
+        ("\"\"\"\r\n  crlf\r\n    lines\r\n  \"\"\"", "crlf\n  lines"),
+        (
+            "\"\"\"\n  \\t escaped indent\n  kept\n\"\"\"",
+            "\t escaped indent\nkept",
+        ),
+        ("\"\"\"\n   \n\"\"\"", ""),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(
+            parse_term(input).unwrap_print(),
+            expected.to_arc_term(),
+            "{input}"
+        );
+    }
+}
+
+#[test]
+fn check_interpolation() {
+    let res = parse_term(r#""Hello ${target}!""#).unwrap_print();
+    let expected = Term::Interpolate(vec![
+        "Hello ".to_arc_term(),
+        Term::get("target").to_arc_term(),
+        "!".to_arc_term(),
+    ]);
+    assert_eq!(res, expected.to_arc_term());
+
+    let res = parse_term(r#""${ (x = "${a}").x }""#).unwrap_print();
+    let inner = Term::Interpolate(vec![Term::get("a").to_arc_term()]);
+    let access = Term::Then {
+        first: [("x", inner)].to_arc_term(),
+        next: Term::get("x").to_arc_term(),
+    };
+    assert_eq!(
+        res,
+        Term::Interpolate(vec![access.to_arc_term()]).to_arc_term()
+    );
+
+    let literal = [
+        (r#""cost: \${price}""#, "cost: ${price}"),
+        (r"'single ${quoted}'", "single ${quoted}"),
+        (r#"r"raw ${text}""#, "raw ${text}"),
+        (r#""just $ sign""#, "just $ sign"),
+    ];
+    for (input, expected) in literal {
+        assert_eq!(
+            parse_term(input).unwrap_print(),
+            expected.to_arc_term(),
+            "{input}"
+        );
+    }
+
+    let input = "\"\"\"\n    host ${name}\n      port ${port}\n    \"\"\"";
+    let expected = Term::Interpolate(vec![
+        "host ".to_arc_term(),
+        Term::get("name").to_arc_term(),
+        "\n  port ".to_arc_term(),
+        Term::get("port").to_arc_term(),
+    ]);
+    assert_eq!(parse_term(input).unwrap_print(), expected.to_arc_term());
+
+    assert!(parse_term(r#"("${a}" = 1)"#).is_err());
+    assert!(parse_term(r#""${}""#).is_err());
+}
+
+#[test]
+fn check_public_api() {
+    let parsed = parse_str("(greet = 'Hello')").unwrap_print();
+    assert_eq!(parsed.term, [("greet", "Hello")].to_arc_term());
+    assert_eq!(parsed.spans.root(), Some(&parsed.term));
+    assert_eq!(parsed.sources.get(parsed.file).unwrap().name, "<string>");
+
+    let parsed = parse_reader("stdin", "{x: #int}".as_bytes()).unwrap_print();
+    let expected = AsTyp([("x", PrimType::Long.to_term())]).to_arc_term();
+    assert_eq!(parsed.term, expected);
+
+    let path = std::env::temp_dir().join(format!("kers-parse-{}.kers", std::process::id()));
+    std::fs::write(&path, "(a = 1,\n b = )").unwrap();
+    let err = parse_file(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(err, ParseError::Syntax { .. }));
+    let rendered = err.render(Style::Plain);
+    assert!(
+        rendered.contains(&format!(" --> {}:2:6\n", path.display())),
+        "{rendered}"
+    );
+    assert!(rendered.contains("2 |  b = )\n"), "{rendered}");
+
+    let err = parse_file(&path).unwrap_err();
+    assert!(matches!(err, ParseError::Io { .. }));
+    assert!(err.render(Style::Plain).starts_with("error: can not read "));
+}
+
+#[test]
+fn check_entries() {
+    let parse = |input, entry| parse_named("entry", input, entry).map(|parsed| parsed.term);
+    let record = [("a", 1i64)].to_arc_term();
+    assert_eq!(
+        parse(" (a = 1) // comment", Entry::Record).unwrap_print(),
+        record
+    );
+    assert_eq!(
+        parse("()", Entry::Record).unwrap_print(),
+        Term::Empty.to_arc_term()
+    );
+    assert!(parse("(1, 2)", Entry::Record).is_err());
+    assert!(parse("(a = 1).a", Entry::Record).is_err());
+
+    let record_type = AsTyp([("a", PrimType::Long.to_term())]).to_arc_term();
+    assert_eq!(
+        parse("{a: #int}", Entry::RecordType).unwrap_print(),
+        record_type
+    );
+    assert_eq!(
+        parse("{}", Entry::RecordType).unwrap_print(),
+        PrimType::Any.to_arc_term()
+    );
+    assert!(parse("{#int, #text}", Entry::RecordType).is_err());
+
+    assert_eq!(
+        parse("(1, 'two')", Entry::Tuple).unwrap_print(),
+        positional([1i64.to_term(), "two".to_term()]).to_arc_term()
+    );
+    assert!(parse("{#int,}", Entry::TupleType).is_ok());
+
+    assert!(parse("{a: #int} -> #text", Entry::Type).is_ok());
+    assert!(parse("#int & {}", Entry::Type).is_ok());
+    let err = parse("(a = 1)", Entry::Type).unwrap_err();
+    assert_eq!(err.to_string(), "entry:1:2: expected a type");
+}
+
+#[test]
+fn check_let() {
+    let res = parse_term("let a = 1; 'b c' = a, in let0 ('in' = 2)").unwrap_print();
+    let body = Term::apply(
+        Term::get("let0").to_arc_term(),
+        [("in", 2i64)].to_arc_term(),
+    );
+    let inner = Term::Let {
+        name: "b c".to_string().into(),
+        value: Term::get("a").to_arc_term(),
+        body: body.to_arc_term(),
+    };
+    assert!(parse_term("(in = 2)").is_err());
+    let expected = Term::Let {
+        name: "a".to_string().into(),
+        value: 1i64.to_arc_term(),
+        body: inner.to_arc_term(),
+    };
+    assert_eq!(res, expected.to_arc_term());
+    assert!(parse_term("let x = 1 in").is_err());
+    assert!(parse_term("let = 1").is_err());
+    assert!(parse_term("x.in").is_err());
+    assert!(parse_term("letx").is_ok());
+}
+
+#[test]
+fn check_import() {
+    let res = parse_term("f import '../base.kers'").unwrap_print();
+    let expected = Term::apply(
+        Term::get("f").to_arc_term(),
+        Term::Import {
+            path: "../base.kers".to_string(),
+            hash: None,
+        }
+        .to_arc_term(),
+    );
+    assert_eq!(res, expected.to_arc_term());
+    let hex = "00ff".repeat(16);
+    let res = parse_term(&format!("import 'a.kers' sha256:{}", hex.to_uppercase())).unwrap_print();
+    let mut digest = [0; 32];
+    digest
+        .iter_mut()
+        .skip(1)
+        .step_by(2)
+        .for_each(|byte| *byte = 0xff);
+    let expected = Term::Import {
+        path: "a.kers".to_string(),
+        hash: Some(Box::new(Digest(digest))),
+    };
+    assert_eq!(res, expected.to_arc_term());
+    assert_eq!(res.to_string(), format!("import 'a.kers' sha256:{hex}"));
+    assert!(parse_term(&format!("import 'a.kers' sha256:{}", &hex[1..])).is_err());
+    assert!(parse_term(&format!("import 'a.kers' sha256:{hex}0")).is_err());
+    assert!(parse_term(&format!("import 'a.kers' sha1:{hex}")).is_err());
+    assert!(parse_term("import \"${x}.kers\"").is_err());
+    assert!(parse_term("import x").is_err());
+    assert!(parse_term("(import = 1)").is_err());
+}
@@ -1,250 +1,698 @@
 use std::sync::Arc;
 
-use pest::iterators::Pair;
+use pest::iterators::{Pair, Pairs};
 
-use crate::{Key, PrimType, Term, ToTerm, Type};
+use crate::{
+    language::source::{FileId, Span, Spans},
+    Digest, Float, Key, PrimType, Primitive, Term, ToTerm, Type,
+};
 
-use super::{Rule, SyntaxError};
+use super::{error::NumberError, Rule, SyntaxError};
 
 type Parsed<'a> = Pair<'a, Rule>;
 type Decoding<A> = Result<A, SyntaxError>;
 type DecodingTerm = Decoding<Arc<Term>>;
 
-pub(super) fn term(term: Parsed) -> DecodingTerm {
-    lam_sequence(term.into_inner().read(Rule::lam_sequence)?)
+/// Builds terms from the parse tree, remembering where every term came from
+pub(super) struct Decoder {
+    file: FileId,
+    spans: Spans,
 }
 
-pub(super) trait PairsExt: Iterator {
-    fn read(&mut self, rule: Rule) -> Result<Self::Item, SyntaxError>;
+/// Children of a parse tree node, along with the node location for error reporting
+struct Children<'a> {
+    span: Span,
+    pairs: Pairs<'a, Rule>,
 }
 
-trait PairExt {
-    fn check(&self, rule: Rule) -> Result<(), SyntaxError>;
-}
-
-impl<'a, I> PairsExt for I
-where
-    I: Iterator<Item = Pair<'a, Rule>>,
-{
-    fn read(&mut self, rule: Rule) -> Result<Self::Item, SyntaxError> {
-        let pair = self
-            .next()
-            .ok_or_else(|| format!("expected {rule:?} got nothing"))?;
-        pair.check(rule)?;
+impl<'a> Children<'a> {
+    fn read(&mut self, rule: Rule) -> Decoding<Parsed<'a>> {
+        let pair = self.pairs.next().ok_or_else(|| SyntaxError::Other {
+            msg: format!("expected {rule:?} got nothing"),
+            span: self.span,
+        })?;
+        let got = pair.as_rule();
+        if got != rule {
+            return Err(SyntaxError::Other {
+                msg: format!("expected {rule:?}, got {got:?}"),
+                span: self.span,
+            });
+        }
         Ok(pair)
     }
 }
 
-impl<'a> PairExt for Pair<'a, Rule> {
-    fn check(&self, rule: Rule) -> Result<(), SyntaxError> {
-        let got = self.as_rule();
-        if got != rule {
-            return Err(format!("expected {rule:?}, got {got:?}"))?;
+impl<'a> Children<'a> {
+    /// Reads leading doc-comments, joining their texts line by line
+    fn docs(&mut self) -> Option<String> {
+        let mut lines = vec![];
+        while let Some(pair) = self
+            .pairs
+            .peek()
+            .filter(|p| p.as_rule() == Rule::doc_comment)
+        {
+            self.pairs.next();
+            lines.extend(doc_lines(pair.as_str()));
         }
-        Ok(())
+        (!lines.is_empty()).then(|| lines.join("\n"))
     }
 }
 
-fn char(input: Parsed) -> Decoding<char> {
-    let input = input.as_str();
-    let mut chars = input.chars();
-    let error = || SyntaxError::CharError(input.to_string());
-    let Some(initial) = chars.next() else {
-        return Err(error());
-    };
-    let Some(second) = chars.next() else {
-        return Ok(initial);
-    };
-    if initial != '\\' {
-        return Err(error());
-    }
-    match second {
-        'u' => {
-            let Ok(code) = input[2..].parse::<u32>() else {
-                return Err(error());
-            };
-            std::char::from_u32(code).ok_or_else(error)
-        }
-        '\"' | '\'' | '\\' | '/' => Ok(second),
-        't' => Ok('\t'),
-        'r' => Ok('\r'),
-        'n' => Ok('\n'),
-        'b' => Ok('\x08'),
-        'f' => Ok('\x0c'),
-        _ => Err(error()),
+/// Text of a doc-comment without comment markers,
+/// the first space after `///` or `*` is dropped as well as blank lines around a block comment
+fn doc_lines(comment: &str) -> Vec<&str> {
+    if let Some(line) = comment.strip_prefix("///") {
+        return vec![line.strip_prefix(' ').unwrap_or(line).trim_end()];
+    }
+    let body = comment.trim_start_matches("/**").trim_end_matches("*/");
+    let mut lines: Vec<_> = body
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            line.strip_prefix(' ').unwrap_or(line).trim_end()
+        })
+        .collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
     }
+    let blank = lines.iter().take_while(|line| line.is_empty()).count();
+    lines.split_off(blank)
 }
 
-fn string(string: Parsed) -> Decoding<String> {
-    let s = string.as_str();
-    let error = || -> SyntaxError { format!("{s} is not a string").into() };
-    let contents = string.into_inner().next().ok_or_else(error)?;
-    contents.into_inner().map(char).collect()
+/// Piece of a string literal
+enum Fragment {
+    Char(char),
+    Escaped(char),
+    Embedded(Arc<Term>),
 }
 
-fn key(key: Parsed) -> Decoding<String> {
-    let mut key = key.into_inner();
-    let first = key.next().ok_or("Empty key")?;
-    match first.as_rule() {
-        Rule::identifier => Ok(first.as_str().to_string()),
-        Rule::string => string(first),
-        rule => Err(format!("Not an identifier {rule:?}").into()),
+impl Fragment {
+    fn is_space(&self) -> bool {
+        matches!(self, Fragment::Char(c) if c.is_whitespace())
     }
 }
 
-fn record(expr: Parsed) -> DecodingTerm {
-    sequence(
-        expr,
-        Rule::assignment,
-        assignment,
-        |left, right| Term::Append { left, right }.to_arc_term(),
-        Assocciation::Left,
-    )
+/// Contents of a multi-line string: blank first and last lines are dropped, other blank lines become empty
+/// and the indentation common to the non-blank lines is stripped.
+/// Escaped chars are neither line breaks nor indentation.
+fn dedent(fragments: Vec<Fragment>) -> Vec<Fragment> {
+    let mut lines: Vec<Vec<Fragment>> = vec![vec![]];
+    for fragment in fragments {
+        match fragment {
+            Fragment::Char('\n') => lines.push(vec![]),
+            fragment => lines.last_mut().unwrap().push(fragment),
+        }
+    }
+    let blank = |line: &Vec<Fragment>| line.iter().all(Fragment::is_space);
+    if lines.len() > 1 && lines.first().is_some_and(blank) {
+        lines.remove(0);
+    }
+    if lines.len() > 1 && lines.last().is_some_and(blank) {
+        lines.pop();
+    }
+    let indent = lines
+        .iter()
+        .filter(|line| !blank(line))
+        .map(|line| line.iter().take_while(|c| c.is_space()).count())
+        .min()
+        .unwrap_or(0);
+    let mut dedented = vec![];
+    for (idx, mut line) in lines.into_iter().enumerate() {
+        if idx > 0 {
+            dedented.push(Fragment::Char('\n'));
+        }
+        if matches!(line.last(), Some(Fragment::Char('\r'))) {
+            line.pop();
+        }
+        if !blank(&line) {
+            dedented.extend(line.into_iter().skip(indent));
+        }
+    }
+    dedented
 }
 
-fn lam_sequence(expr: Parsed) -> DecodingTerm {
-    sequence(
-        expr,
-        Rule::intersection,
-        intersection,
-        |dom, codom| Type::Function { dom, codom }.to_arc_term(),
-        Assocciation::Right,
-    )
-}
+impl<'a> Iterator for Children<'a> {
+    type Item = Parsed<'a>;
 
-fn intersection(expr: Parsed) -> DecodingTerm {
-    sequence(
-        expr,
-        Rule::application,
-        application,
-        |left, right| Type::And { left, right }.to_arc_term(),
-        Assocciation::Left,
-    )
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pairs.next()
+    }
 }
 
-fn application(expr: Parsed) -> DecodingTerm {
-    sequence(
-        expr,
-        Rule::then_chain,
-        then_chain,
-        |func, args| Term::apply(func, args).to_arc_term(),
-        Assocciation::Left,
-    )
+enum Assocciation {
+    Left,
+    Right,
 }
 
-fn then_chain(expr: Parsed) -> DecodingTerm {
-    sequence(
-        expr,
-        Rule::modified_term,
-        modifed_term,
-        |first, next| Term::Then { first, next }.to_arc_term(),
-        Assocciation::Left,
-    )
-}
+impl Decoder {
+    pub(super) fn new(file: FileId) -> Self {
+        Decoder {
+            file,
+            spans: Spans::default(),
+        }
+    }
 
-fn record_type(expr: Parsed) -> DecodingTerm {
-    sequence(
-        expr,
-        Rule::ascription,
-        ascription,
-        |left, right| Type::And { left, right }.to_arc_term(),
-        Assocciation::Left,
-    )
-}
+    /// Decodes the whole `file` rule or a form parsed by one of the entry rules
+    pub(super) fn entry(mut self, pair: Parsed) -> Decoding<(Arc<Term>, Spans)> {
+        let term = match pair.as_rule() {
+            Rule::file => self.term(self.children(pair).read(Rule::term)?)?,
+            _ => self.atomic(pair)?,
+        };
+        self.spans.set_root(term.clone());
+        Ok((term, self.spans))
+    }
 
-fn key_value_pair<R: ToTerm>(
-    input: Parsed,
-    fterm: impl FnOnce(Key, Arc<Term>) -> R,
-) -> DecodingTerm {
-    let mut input = input.into_inner();
-    let name = key(input.read(Rule::key)?)?.into();
-    let value = term(input.read(Rule::term)?)?;
-    fterm(name, value).to_arc_ok()
-}
+    fn span(&self, pair: &Parsed) -> Span {
+        Span::from_pest(self.file, pair.as_span())
+    }
 
-fn assignment(input: Parsed) -> DecodingTerm {
-    key_value_pair(input, |name, value| Term::Set { name, value })
-}
+    fn error(&self, pair: &Parsed, msg: String) -> SyntaxError {
+        SyntaxError::Other {
+            msg,
+            span: self.span(pair),
+        }
+    }
 
-fn ascription(expr: Parsed) -> DecodingTerm {
-    key_value_pair(expr, |name, typ| Type::Field { name, typ })
-}
+    fn children<'a>(&self, pair: Parsed<'a>) -> Children<'a> {
+        Children {
+            span: self.span(&pair),
+            pairs: pair.into_inner(),
+        }
+    }
 
-fn modifed_term(expr: Parsed) -> DecodingTerm {
-    let mut subs = expr.into_inner().rev();
-    let atomic = atomic_term(subs.read(Rule::atomic_term)?)?;
-    subs.try_fold(atomic, |term, sub: Pair<'_, Rule>| {
-        sub.check(Rule::modifier)?;
-        match sub.as_str() {
-            "@" => Term::Unlambda(term).to_arc_ok(),
-            s => Err(format!("Unknown modifier {s}").into()),
+    fn first<'a>(&self, pair: Parsed<'a>, what: &str) -> Decoding<Parsed<'a>> {
+        let span = self.span(&pair);
+        pair.into_inner().next().ok_or_else(|| SyntaxError::Other {
+            msg: format!("Empty {what}"),
+            span,
+        })
+    }
+
+    fn check(&self, pair: &Parsed, rule: Rule) -> Decoding<()> {
+        let got = pair.as_rule();
+        if got != rule {
+            return Err(self.error(pair, format!("expected {rule:?}, got {got:?}")));
         }
-    })
-}
+        Ok(())
+    }
 
-fn internal(expr: Parsed) -> DecodingTerm {
-    let sub = expr.into_inner().next().ok_or("Empty internal")?;
-    match sub.as_rule() {
-        Rule::internal_int => PrimType::Long.to_arc_ok(),
-        Rule::internal_text => PrimType::Text.to_arc_ok(),
-        other => Err(format!("Expecting internal, got {other:?}").into()),
+    fn spanned(&mut self, pair: &Parsed, term: impl ToTerm) -> DecodingTerm {
+        let term = term.to_arc_term();
+        self.spans.mark(&term, self.span(pair));
+        Ok(term)
     }
-}
 
-fn atomic_term(term: Parsed) -> DecodingTerm {
-    let term = term.into_inner().next().ok_or("Empty Term")?;
-
-    match term.as_rule() {
-        Rule::record => record(term),
-        Rule::string => string(term)?.to_arc_ok(),
-        Rule::natural => natural(term)?.to_arc_ok(),
-        Rule::identifier => get(term),
-        Rule::reflect => Term::Reflect.to_arc_ok(),
-        Rule::record_type => record_type(term), // Add missing function call
-        Rule::universe => PrimType::Universe.to_arc_ok(),
-        Rule::empty => Term::Empty.to_arc_ok(),
-        Rule::internal => internal(term),
-        Rule::unit_type => PrimType::Any.to_arc_ok(),
-        rule => Err(format!("Not an atomic term {rule:?}").into()), // Rule::string =>
+    fn term(&mut self, term: Parsed) -> DecodingTerm {
+        let inner = self.first(term, "term")?;
+        match inner.as_rule() {
+            Rule::let_term => self.let_term(inner),
+            Rule::lambda => self.lambda(inner),
+            Rule::lam_sequence => self.lam_sequence(inner),
+            rule => Err(self.error(&inner, format!("Not a term {rule:?}"))),
+        }
     }
-}
 
-enum Assocciation {
-    Left,
-    Right,
-}
+    fn char(&self, pair: Parsed) -> Decoding<char> {
+        let input = pair.as_str();
+        let mut chars = input.chars();
+        let error = || SyntaxError::CharError {
+            text: input.to_string(),
+            span: self.span(&pair),
+        };
+        let Some(initial) = chars.next() else {
+            return Err(error());
+        };
+        let Some(second) = chars.next() else {
+            return Ok(initial);
+        };
+        if initial != '\\' {
+            return Err(error());
+        }
+        match second {
+            'u' => {
+                let hex = input[2..].trim_start_matches('{').trim_end_matches('}');
+                let Ok(code) = u32::from_str_radix(hex, 16) else {
+                    return Err(error());
+                };
+                // surrogates and values above the unicode range are not chars
+                std::char::from_u32(code).ok_or_else(error)
+            }
+            '\"' | '\'' | '\\' | '/' | '$' => Ok(second),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            'n' => Ok('\n'),
+            'b' => Ok('\x08'),
+            'f' => Ok('\x0c'),
+            _ => Err(error()),
+        }
+    }
 
-fn sequence<A: Default>(
-    expr: Parsed,
-    inner_rule: Rule,
-    inner: impl Fn(Parsed) -> Decoding<A>,
-    combine: impl Fn(A, A) -> A,
-    association: Assocciation,
-) -> Decoding<A> {
-    let inners = expr.into_inner().map(|s| {
-        s.check(inner_rule)?;
-        inner(s)
-    });
-
-    match association {
-        Assocciation::Left => combine_sequence(inners, combine),
-        Assocciation::Right => combine_sequence(inners.rev(), combine),
+    fn string(&mut self, string: Parsed) -> Decoding<Vec<Fragment>> {
+        let contents = self.first(string, "string")?;
+        match contents.as_rule() {
+            Rule::raw_string => {
+                let raw = self.first(contents, "raw string")?;
+                Ok(raw.as_str().chars().map(Fragment::Char).collect())
+            }
+            Rule::multiline_string => Ok(dedent(self.fragments(contents)?)),
+            _ => self.fragments(contents),
+        }
     }
-}
 
-fn combine_sequence<A: Default>(
-    iter: impl Iterator<Item = Decoding<A>>,
-    combine: impl Fn(A, A) -> A,
-) -> Decoding<A> {
-    iter.reduce(|left, right| Ok(combine(left?, right?)))
-        .unwrap_or_else(|| Ok(A::default()))
-}
+    fn fragments(&mut self, contents: Parsed) -> Decoding<Vec<Fragment>> {
+        let mut fragments = vec![];
+        for sub in contents.into_inner() {
+            fragments.push(match sub.as_rule() {
+                Rule::interpolation => {
+                    let term = self.children(sub).read(Rule::term)?;
+                    Fragment::Embedded(self.term(term)?)
+                }
+                _ if sub.as_str().starts_with('\\') => Fragment::Escaped(self.char(sub)?),
+                _ => Fragment::Char(self.char(sub)?),
+            })
+        }
+        Ok(fragments)
+    }
 
-fn natural(term: Parsed) -> Decoding<u64> {
-    Ok(term.as_str().parse()?)
-}
+    /// String literal term, a text unless there are embedded terms to interpolate
+    fn interpolated(&mut self, string: Parsed) -> DecodingTerm {
+        let mut parts = vec![];
+        let mut text = String::new();
+        for fragment in self.string(string.clone())? {
+            match fragment {
+                Fragment::Char(c) | Fragment::Escaped(c) => text.push(c),
+                Fragment::Embedded(term) => {
+                    if !text.is_empty() {
+                        parts.push(std::mem::take(&mut text).to_arc_term());
+                    }
+                    parts.push(term);
+                }
+            }
+        }
+        if parts.is_empty() {
+            return self.spanned(&string, text);
+        }
+        if !text.is_empty() {
+            parts.push(text.to_arc_term());
+        }
+        self.spanned(&string, Term::Interpolate(parts))
+    }
+
+    fn key(&mut self, key: Parsed) -> Decoding<Key> {
+        let first = self.first(key, "key")?;
+        match first.as_rule() {
+            Rule::identifier => Ok(first.as_str().to_string().into()),
+            Rule::string => Ok(self.literal(first, "keys")?.into()),
+            rule => Err(self.error(&first, format!("Not an identifier {rule:?}"))),
+        }
+    }
+
+    /// Text of the string literal, `what` the literal is tells why interpolation is not allowed
+    fn literal(&mut self, string: Parsed, what: &str) -> Decoding<String> {
+        let span = self.span(&string);
+        let fragments = self.string(string)?;
+        let chars = fragments.into_iter().map(|fragment| match fragment {
+            Fragment::Char(c) | Fragment::Escaped(c) => Ok(c),
+            Fragment::Embedded(_) => Err(SyntaxError::Other {
+                msg: format!("Interpolation is not allowed in {what}"),
+                span,
+            }),
+        });
+        chars.collect()
+    }
 
-fn get(term: Parsed) -> DecodingTerm {
-    let name = term.as_str().to_string().into();
-    Term::Get(name).to_arc_ok()
+    fn import(&mut self, expr: Parsed) -> DecodingTerm {
+        let mut subs = self.children(expr.clone());
+        let path = self.literal(subs.read(Rule::string)?, "import paths")?;
+        let hash = match subs.next() {
+            Some(integrity) => Some(Box::new(self.digest(&integrity)?)),
+            None => None,
+        };
+        self.spanned(&expr, Term::Import { path, hash })
+    }
+
+    fn digest(&self, integrity: &Parsed) -> Decoding<Digest> {
+        let hex = integrity.as_str().trim_start_matches("sha256:");
+        let mut digest = [0; 32];
+        for (idx, byte) in digest.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * idx..2 * idx + 2], 16)
+                .map_err(|err| self.error(integrity, format!("Invalid hash: {err}")))?;
+        }
+        Ok(Digest(digest))
+    }
+
+    fn record(&mut self, expr: Parsed) -> DecodingTerm {
+        self.sequence(
+            expr,
+            Rule::assignment,
+            Self::assignment,
+            |left, right| Term::Append { left, right }.to_arc_term(),
+            Assocciation::Left,
+        )
+    }
+
+    fn lambda(&mut self, expr: Parsed) -> DecodingTerm {
+        let mut subs = self.children(expr.clone());
+        let dom = self.then_chain(subs.read(Rule::then_chain)?)?;
+        let body = self.term(subs.read(Rule::term)?)?;
+        self.spanned(&expr, Term::Lambda { dom, body })
+    }
+
+    /// Nested `Term::Let`s, one per binding, every one spanning from its binding to the end
+    fn let_term(&mut self, expr: Parsed) -> DecodingTerm {
+        let whole = self.span(&expr);
+        let mut subs: Vec<_> = expr.clone().into_inner().collect();
+        let Some(body) = subs.pop() else {
+            return Err(self.error(&expr, "Empty let".to_string()));
+        };
+        self.check(&body, Rule::term)?;
+        let mut term = self.term(body)?;
+        for binding in subs.into_iter().rev() {
+            self.check(&binding, Rule::assignment)?;
+            let span = Span {
+                end: whole.end,
+                ..self.span(&binding)
+            };
+            let mut parts = self.children(binding);
+            let doc = parts.docs();
+            let name = self.key(parts.read(Rule::key)?)?;
+            let value = self.term(parts.read(Rule::term)?)?;
+            term = Term::Let {
+                name,
+                value,
+                body: term,
+            }
+            .to_arc_term();
+            self.spans.mark(&term, span);
+            if let Some(doc) = doc {
+                self.spans.set_doc(&term, doc);
+            }
+        }
+        self.spans.set(&term, whole);
+        Ok(term)
+    }
+
+    fn lam_sequence(&mut self, expr: Parsed) -> DecodingTerm {
+        self.sequence(
+            expr,
+            Rule::intersection,
+            Self::intersection,
+            |dom, codom| Type::Function { dom, codom }.to_arc_term(),
+            Assocciation::Right,
+        )
+    }
+
+    fn intersection(&mut self, expr: Parsed) -> DecodingTerm {
+        self.sequence(
+            expr,
+            Rule::application,
+            Self::application,
+            |left, right| Type::And { left, right }.to_arc_term(),
+            Assocciation::Left,
+        )
+    }
+
+    fn application(&mut self, expr: Parsed) -> DecodingTerm {
+        let arguments = expr.clone().into_inner().count().saturating_sub(1);
+        let applied = self.sequence(
+            expr,
+            Rule::then_chain,
+            Self::then_chain,
+            |func, args| Term::apply(func, args).to_arc_term(),
+            Assocciation::Left,
+        )?;
+        // the function is applied at the argument, mismatched arguments are reported there
+        let mut term = &applied;
+        for _ in 0..arguments {
+            let Term::Then { first, next } = &**term else {
+                break;
+            };
+            let Term::Unlambda(func) = &**next else {
+                break;
+            };
+            if let Some(span) = self.spans.get(first) {
+                self.spans.set(next, span);
+            }
+            term = func;
+        }
+        Ok(applied)
+    }
+
+    fn then_chain(&mut self, expr: Parsed) -> DecodingTerm {
+        let whole = self.span(&expr);
+        let mut links = vec![];
+        for link in expr.into_inner() {
+            let span = self.span(&link);
+            let term = match link.as_rule() {
+                Rule::index => {
+                    let idx = self.index(&link)?;
+                    self.spanned(&link, Term::Get(idx.into()))?
+                }
+                _ => {
+                    self.check(&link, Rule::modified_term)?;
+                    self.modifed_term(link)?
+                }
+            };
+            links.push((term, span));
+        }
+        self.combine_sequence(whole, links, |first, next| {
+            Term::Then { first, next }.to_arc_term()
+        })
+    }
+
+    fn record_type(&mut self, expr: Parsed) -> DecodingTerm {
+        self.sequence(
+            expr,
+            Rule::ascription,
+            Self::ascription,
+            |left, right| Type::And { left, right }.to_arc_term(),
+            Assocciation::Left,
+        )
+    }
+
+    fn tuple(&mut self, expr: Parsed) -> DecodingTerm {
+        self.positional(
+            expr,
+            |name, value| Term::Set { name, value },
+            |left, right| Term::Append { left, right }.to_arc_term(),
+        )
+    }
+
+    fn tuple_type(&mut self, expr: Parsed) -> DecodingTerm {
+        self.positional(
+            expr,
+            |name, typ| Type::Field { name, typ },
+            |left, right| Type::And { left, right }.to_arc_term(),
+        )
+    }
+
+    fn positional<R: ToTerm>(
+        &mut self,
+        expr: Parsed,
+        fterm: impl Fn(Key, Arc<Term>) -> R,
+        combine: impl Fn(Arc<Term>, Arc<Term>) -> Arc<Term>,
+    ) -> DecodingTerm {
+        let whole = self.span(&expr);
+        let mut elems = vec![];
+        for (idx, elem) in expr.into_inner().enumerate() {
+            self.check(&elem, Rule::term)?;
+            let span = self.span(&elem);
+            let value = self.term(elem.clone())?;
+            elems.push((self.spanned(&elem, fterm(Key::Index(idx), value))?, span));
+        }
+        self.combine_sequence(whole, elems, combine)
+    }
+
+    fn key_value_pair<R: ToTerm>(
+        &mut self,
+        input: Parsed,
+        fterm: impl FnOnce(Key, Arc<Term>) -> R,
+    ) -> DecodingTerm {
+        let mut subs = self.children(input.clone());
+        let doc = subs.docs();
+        let key = subs.read(Rule::key)?;
+        let span = Span {
+            end: subs.span.end,
+            ..self.span(&key)
+        };
+        let name = self.key(key)?;
+        let value = self.term(subs.read(Rule::term)?)?;
+        let entry = fterm(name, value).to_arc_term();
+        self.spans.mark(&entry, span);
+        if let Some(doc) = doc {
+            self.spans.set_doc(&entry, doc);
+        }
+        Ok(entry)
+    }
+
+    fn assignment(&mut self, input: Parsed) -> DecodingTerm {
+        self.key_value_pair(input, |name, value| Term::Set { name, value })
+    }
+
+    fn ascription(&mut self, expr: Parsed) -> DecodingTerm {
+        self.key_value_pair(expr, |name, typ| Type::Field { name, typ })
+    }
+
+    fn modifed_term(&mut self, expr: Parsed) -> DecodingTerm {
+        let mut subs: Vec<_> = expr.clone().into_inner().collect();
+        let Some(atomic) = subs.pop() else {
+            return Err(self.error(&expr, "Empty modified term".to_string()));
+        };
+        self.check(&atomic, Rule::atomic_term)?;
+        let atomic = self.atomic_term(atomic)?;
+        subs.into_iter().rev().try_fold(atomic, |term, sub| {
+            self.check(&sub, Rule::modifier)?;
+            match sub.as_str() {
+                "@" => self.spanned(&expr, Term::Unlambda(term)),
+                s => Err(self.error(&sub, format!("Unknown modifier {s}"))),
+            }
+        })
+    }
+
+    fn internal(&mut self, expr: Parsed) -> DecodingTerm {
+        let sub = self.first(expr.clone(), "internal")?;
+        match sub.as_rule() {
+            Rule::internal_int => self.spanned(&expr, PrimType::Long),
+            Rule::internal_float => self.spanned(&expr, PrimType::Float),
+            Rule::internal_text => self.spanned(&expr, PrimType::Text),
+            other => Err(self.error(&sub, format!("Expecting internal, got {other:?}"))),
+        }
+    }
+
+    fn atomic_term(&mut self, term: Parsed) -> DecodingTerm {
+        let term = self.first(term, "Term")?;
+        self.atomic(term)
+    }
+
+    fn atomic(&mut self, term: Parsed) -> DecodingTerm {
+        match term.as_rule() {
+            Rule::record => self.record(term),
+            Rule::term => self.term(term),
+            Rule::string => self.interpolated(term),
+            Rule::natural => {
+                let natural = self.natural(&term)?;
+                self.spanned(&term, natural)
+            }
+            Rule::identifier => self.get(term),
+            Rule::reflect => self.spanned(&term, Term::Reflect),
+            Rule::tuple => self.tuple(term),
+            Rule::record_type => self.record_type(term), // Add missing function call
+            Rule::tuple_type => self.tuple_type(term),
+            Rule::universe => self.spanned(&term, PrimType::Universe),
+            Rule::empty => self.spanned(&term, Term::Empty),
+            Rule::internal => self.internal(term),
+            Rule::import => self.import(term),
+            Rule::unit_type => self.spanned(&term, PrimType::Any),
+            rule => Err(self.error(&term, format!("Not an atomic term {rule:?}"))), // Rule::string =>
+        }
+    }
+
+    fn sequence(
+        &mut self,
+        expr: Parsed,
+        inner_rule: Rule,
+        inner: fn(&mut Self, Parsed) -> DecodingTerm,
+        combine: impl Fn(Arc<Term>, Arc<Term>) -> Arc<Term>,
+        association: Assocciation,
+    ) -> DecodingTerm {
+        let whole = self.span(&expr);
+        let mut inners = vec![];
+        for s in expr.into_inner() {
+            self.check(&s, inner_rule)?;
+            let span = self.span(&s);
+            inners.push((inner(self, s)?, span));
+        }
+
+        match association {
+            Assocciation::Left => self.combine_sequence(whole, inners, combine),
+            Assocciation::Right => {
+                inners.reverse();
+                self.combine_sequence(whole, inners, |right, left| combine(left, right))
+            }
+        }
+    }
+
+    /// Combines terms pairwise, every combined term spans from the first to the last of its parts,
+    /// the resulting term spans the `whole` sequence
+    fn combine_sequence(
+        &mut self,
+        whole: Span,
+        terms: Vec<(Arc<Term>, Span)>,
+        combine: impl Fn(Arc<Term>, Arc<Term>) -> Arc<Term>,
+    ) -> DecodingTerm {
+        let parts = terms.len();
+        let combined = terms
+            .into_iter()
+            .reduce(|(left, left_span), (right, right_span)| {
+                let span = Span {
+                    start: left_span.start.min(right_span.start),
+                    end: left_span.end.max(right_span.end),
+                    ..if left_span.start <= right_span.start {
+                        left_span
+                    } else {
+                        right_span
+                    }
+                };
+                let term = combine(left, right);
+                self.spans.mark(&term, span);
+                (term, span)
+            });
+        let combined = combined.map(|(term, _)| term).unwrap_or_default();
+        if parts > 1 {
+            self.spans.set(&combined, whole);
+        }
+        Ok(combined)
+    }
+
+    /// Decodes integer or float literal, underscores are only separating digits
+    fn natural(&self, term: &Parsed) -> Decoding<Primitive> {
+        let literal = self.first(term.clone(), "number")?;
+        let sign = if term.as_str().starts_with('-') {
+            "-"
+        } else {
+            ""
+        };
+        let digits = literal.as_str().replace('_', "");
+        let integer = |digits: &str, radix| {
+            i64::from_str_radix(&format!("{sign}{digits}"), radix)
+                .map(Primitive::Long)
+                .map_err(NumberError::from)
+        };
+        let number = match literal.as_rule() {
+            Rule::hex => integer(&digits[2..], 16),
+            Rule::octal => integer(&digits[2..], 8),
+            Rule::binary => integer(&digits[2..], 2),
+            Rule::decimal => integer(&digits, 10),
+            Rule::float => {
+                let float = format!("{sign}{digits}").parse::<f64>();
+                match float {
+                    Ok(x) if x.is_infinite() => Err(NumberError::FloatOverflow),
+                    Ok(x) => Ok(Primitive::Float(Float(x))),
+                    Err(err) => Err(NumberError::from(err)),
+                }
+            }
+            rule => return Err(self.error(&literal, format!("Not a number {rule:?}"))),
+        };
+        number.map_err(|source| SyntaxError::ParseNumberError {
+            source,
+            span: self.span(term),
+        })
+    }
+
+    fn index(&self, term: &Parsed) -> Decoding<usize> {
+        term.as_str()
+            .parse()
+            .map_err(
+                |source: std::num::ParseIntError| SyntaxError::ParseNumberError {
+                    source: source.into(),
+                    span: self.span(term),
+                },
+            )
+    }
+
+    fn get(&mut self, term: Parsed) -> DecodingTerm {
+        let name: Key = term.as_str().to_string().into();
+        self.spanned(&term, Term::Get(name))
+    }
 }
@@ -1,28 +1,64 @@
-use pest::error::Error as PestError;
+use pest::error::{Error as PestError, InputLocation, LineColLocation};
 use thiserror::Error;
 
+use crate::language::source::{FileId, Span};
+
 use super::Rule;
 
 #[derive(Error, Debug)]
 pub enum SyntaxError {
-    #[error("parse error {0}")]
-    ParseError(#[from] PestError<Rule>),
-    #[error("{0}")]
-    ParseNumberError(#[from] std::num::ParseIntError),
-    #[error("invalid character: {0}")]
-    CharError(String),
-    #[error("{msg}")]
-    Other { msg: String },
+    #[error("parse error {error}")]
+    ParseError {
+        error: Box<PestError<Rule>>,
+        file: FileId,
+    },
+    #[error("{span}: {source}")]
+    ParseNumberError { source: NumberError, span: Span },
+    #[error("{span}: invalid character: {text}")]
+    CharError { text: String, span: Span },
+    #[error("{span}: {msg}")]
+    Other { msg: String, span: Span },
 }
 
-impl From<String> for SyntaxError {
-    fn from(s: String) -> Self {
-        SyntaxError::Other { msg: s }
-    }
+#[derive(Error, Debug)]
+pub enum NumberError {
+    #[error(transparent)]
+    Int(#[from] std::num::ParseIntError),
+    #[error(transparent)]
+    Float(#[from] std::num::ParseFloatError),
+    #[error("number too large to fit in a float")]
+    FloatOverflow,
 }
 
-impl From<&str> for SyntaxError {
-    fn from(s: &str) -> Self {
-        s.to_string().into()
+impl SyntaxError {
+    pub(crate) fn parse_error(error: PestError<Rule>, file: FileId) -> Self {
+        SyntaxError::ParseError {
+            error: Box::new(error),
+            file,
+        }
+    }
+
+    /// Location of the offending source
+    pub fn span(&self) -> Span {
+        match self {
+            SyntaxError::ParseError { error, file } => {
+                let (start, end) = match error.location {
+                    InputLocation::Pos(pos) => (pos, pos),
+                    InputLocation::Span(span) => span,
+                };
+                let (LineColLocation::Pos((line, col)) | LineColLocation::Span((line, col), _)) =
+                    error.line_col;
+                Span {
+                    file: *file,
+                    start,
+                    end,
+                    line,
+                    col,
+                }
+            }
+            SyntaxError::ParseNumberError { span, .. }
+            | SyntaxError::CharError { span, .. }
+            | SyntaxError::Other { span, .. } => *span,
+        }
     }
 }
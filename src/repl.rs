@@ -0,0 +1,360 @@
+use std::sync::Arc;
+
+use crate::{
+    diagnostics::{Diagnostic, Style},
+    evaltime::{
+        checking::TypeChecking,
+        evaluate::Evaluation,
+        values::{merge_fields, TypeValue, Value},
+    },
+    language::source::{NodeId, Sources, Span, Spans},
+    parse::{parse_entry, Entry},
+    Term,
+};
+
+const HELP: &str = "\
+name = value     add the fields to the context
+term             evaluate the term in the context
+:type term       print the inferred type of the term
+:ast term        print the syntax tree of the term
+:context         print the syntax tree of the context
+:help            print this message
+:quit            end the session";
+
+/// Response of the session to a line of input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// The input is not finished yet, the next line continues it
+    More,
+    Output(String),
+    /// Rendered diagnostic, the session goes on
+    Error(String),
+    Quit,
+}
+
+/// Interactive session, entries are evaluated in the context record accumulated from the previous definitions
+pub struct Repl {
+    /// Definitions so far, appended one to another
+    context: Arc<Term>,
+    value: Value<()>,
+    typ: Value<()>,
+    checking: TypeChecking<()>,
+    evaluation: Evaluation<()>,
+    sources: Sources,
+    spans: Vec<Spans>,
+    buffer: String,
+    style: Style,
+}
+
+impl Repl {
+    pub fn new(style: Style) -> Self {
+        Repl {
+            context: Arc::new(Term::Empty),
+            value: Value::empty(),
+            typ: Value::record_type(vec![]),
+            checking: TypeChecking::new(()),
+            evaluation: Evaluation::new(()),
+            sources: Sources::default(),
+            spans: vec![],
+            buffer: String::new(),
+            style,
+        }
+    }
+
+    /// Prompt to show before reading the next line
+    pub fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() {
+            "kers> "
+        } else {
+            "...   "
+        }
+    }
+
+    /// Record term of all the definitions made in the session
+    pub fn context(&self) -> &Arc<Term> {
+        &self.context
+    }
+
+    /// Handles a line of input, complete entries are executed and incomplete are buffered
+    pub fn feed(&mut self, line: &str) -> Reply {
+        if !self.buffer.is_empty() {
+            self.buffer.push('\n');
+        }
+        self.buffer.push_str(line);
+        if is_incomplete(&self.buffer) {
+            return Reply::More;
+        }
+        let input = std::mem::take(&mut self.buffer);
+        let input = input.trim();
+        let (command, arg) = match input.strip_prefix(':') {
+            Some(command) => command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, "")),
+            None => ("", input),
+        };
+        match command {
+            _ if input.is_empty() => Reply::Output(String::new()),
+            "" => self.entry(input),
+            "type" | "t" => self.typ_of(arg),
+            "ast" => self
+                .parse(arg, Entry::Term)
+                .map_or_else(Reply::Error, |term| Reply::Output(format!("{term:#?}"))),
+            "context" => Reply::Output(format!("{:#?}", self.context)),
+            "help" | "h" => Reply::Output(HELP.to_string()),
+            "quit" | "q" => Reply::Quit,
+            other => Reply::Error(format!("unknown command :{other}, see :help")),
+        }
+    }
+
+    /// Checks and evaluates a term, or adds the fields of `name = value, ...` to the context
+    fn entry(&mut self, input: &str) -> Reply {
+        let term = match self.parse(input, Entry::Term) {
+            Ok(term) => term,
+            Err(err) => match self.parse(&format!("({input})"), Entry::Record) {
+                Ok(fields) => return self.define(fields),
+                Err(_) => return Reply::Error(err),
+            },
+        };
+        if let Err(err) = self.check(&term) {
+            return Reply::Error(err);
+        }
+        match self.evaluation.eval(&term, &self.value) {
+            Ok(value) => Reply::Output(value.to_string()),
+            Err(err) => {
+                let span = self.span(self.evaluation.failed_at());
+                Reply::Error(self.render(Diagnostic::evaluation(&err, span)))
+            }
+        }
+    }
+
+    fn define(&mut self, fields: Arc<Term>) -> Reply {
+        let typ = match self.check(&fields) {
+            Ok(typ) => typ,
+            Err(err) => return Reply::Error(err),
+        };
+        let value = match self.evaluation.eval(&fields, &self.value) {
+            Ok(value) => value,
+            Err(err) => {
+                let span = self.span(self.evaluation.failed_at());
+                return Reply::Error(self.render(Diagnostic::evaluation(&err, span)));
+            }
+        };
+        let output = value.to_string();
+        if let (
+            Value::Type(TypeValue::Record { fields: context }),
+            Value::Type(TypeValue::Record { fields: defined }),
+        ) = (&mut self.typ, typ)
+        {
+            merge_fields(context, defined);
+        }
+        if let (Value::Record { fields: context }, Value::Record { fields: defined }) =
+            (&mut self.value, value)
+        {
+            merge_fields(context, defined);
+        }
+        self.context = Arc::new(Term::Append {
+            left: self.context.clone(),
+            right: fields,
+        });
+        Reply::Output(output)
+    }
+
+    fn typ_of(&mut self, input: &str) -> Reply {
+        let term = match self.parse(input, Entry::Term) {
+            Ok(term) => term,
+            Err(err) => return Reply::Error(err),
+        };
+        self.check(&term)
+            .map_or_else(Reply::Error, |typ| Reply::Output(typ.to_string()))
+    }
+
+    /// Type of the term in the context of the session, errors are rendered
+    fn check(&mut self, term: &Arc<Term>) -> Result<Value<()>, String> {
+        self.checking.check(term, &self.typ).map_err(|err| {
+            let span = self.span(self.checking.failed_at());
+            self.render(Diagnostic::typing(&err, span))
+        })
+    }
+
+    /// Parses the input remembering it for the diagnostics, errors are rendered
+    fn parse(&mut self, input: &str, entry: Entry) -> Result<Arc<Term>, String> {
+        let file = self
+            .sources
+            .add(format!("<repl:{}>", self.spans.len() + 1), input);
+        match parse_entry(input, file, entry) {
+            Ok((term, spans)) => {
+                self.spans.push(spans);
+                Ok(term)
+            }
+            Err(err) => {
+                self.spans.push(Spans::default());
+                Err(self.render(Diagnostic::syntax(&err)))
+            }
+        }
+    }
+
+    /// Location of the node in any of the entries of the session
+    fn span(&self, node: Option<NodeId>) -> Option<Span> {
+        let node = node?;
+        self.spans
+            .iter()
+            .rev()
+            .find_map(|spans| spans.get_node(node))
+    }
+
+    fn render(&self, diagnostic: Diagnostic) -> String {
+        diagnostic.render(&self.sources, self.style)
+    }
+}
+
+/// Whether the input has unclosed brackets, strings or comments, so it continues on the next line
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        let skipped = match c {
+            '(' | '{' => {
+                depth += 1;
+                1
+            }
+            ')' | '}' => {
+                depth -= 1;
+                1
+            }
+            '/' if rest.starts_with("//") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => match block_comment_end(rest) {
+                Some(end) => end,
+                None => return true,
+            },
+            '"' if rest.starts_with("\"\"\"") => match rest[3..].find("\"\"\"") {
+                Some(end) => end + 6,
+                None => return true,
+            },
+            '"' | '\'' => quoted_end(rest, c).unwrap_or(rest.len()),
+            _ => c.len_utf8(),
+        };
+        rest = &rest[skipped..];
+    }
+    depth > 0
+}
+
+/// Length of the quoted text at the start of the input, including the quotes
+fn quoted_end(text: &str, quote: char) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1);
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' => return Some(idx),
+            c if c == quote => return Some(idx + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Length of the nested block comment at the start of the input
+fn block_comment_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut idx = 0;
+    while idx < text.len() {
+        if text[idx..].starts_with("/*") {
+            depth += 1;
+            idx += 2;
+        } else if text[idx..].starts_with("*/") {
+            depth -= 1;
+            idx += 2;
+            if depth == 0 {
+                return Some(idx);
+            }
+        } else {
+            idx += text[idx..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+fn outputs(repl: &mut Repl, lines: &[&str]) -> Vec<Reply> {
+    lines.iter().map(|line| repl.feed(line)).collect()
+}
+
+#[cfg(test)]
+fn output(text: &str) -> Reply {
+    Reply::Output(text.to_string())
+}
+
+#[test]
+fn repl_accumulates_definitions() {
+    let mut repl = Repl::new(Style::Plain);
+    let replies = outputs(
+        &mut repl,
+        &[
+            "a = 1",
+            "b = \"b is ${a}\", c = 2",
+            "(b, c)",
+            "a = 'one'",
+            "a",
+        ],
+    );
+    assert_eq!(
+        replies,
+        vec![
+            output("(a = 1)"),
+            output("(b = 'b is 1', c = 2)"),
+            output("('b is 1', 2)"),
+            output("(a = 'one')"),
+            output("'one'"),
+        ]
+    );
+    assert!(matches!(&**repl.context(), Term::Append { .. }));
+    assert_eq!(repl.feed(":type (a, b)"), output("{#text, #text}"));
+}
+
+#[test]
+fn repl_multiline_input() {
+    let mut repl = Repl::new(Style::Plain);
+    assert_eq!(repl.feed("point = ("), Reply::More);
+    assert_eq!(repl.prompt(), "...   ");
+    assert_eq!(repl.feed("  x = 1, // ("), Reply::More);
+    assert_eq!(repl.feed("  y = '{'"), Reply::More);
+    assert_eq!(repl.feed(")"), output("(point = (x = 1, y = '{'))"));
+    assert_eq!(repl.prompt(), "kers> ");
+    assert_eq!(repl.feed(":t {"), Reply::More);
+    assert_eq!(repl.feed("x: #int}"), output("*"));
+    assert!(is_incomplete("'(' \"\"\"\nstill a string"));
+    assert!(!is_incomplete("())"));
+}
+
+#[test]
+fn repl_survives_errors() {
+    let mut repl = Repl::new(Style::Plain);
+    let Reply::Error(err) = repl.feed("a = missing") else {
+        panic!("expected an error")
+    };
+    assert!(err.contains("unbound name `missing`"), "{err}");
+    let Reply::Error(err) = repl.feed("(x = 1).y") else {
+        panic!("expected an error")
+    };
+    assert!(err.contains("<repl:"), "{err}");
+    assert!(matches!(repl.feed("(a = "), Reply::More));
+    assert!(matches!(repl.feed(") )"), Reply::Error(_)));
+    assert!(matches!(repl.feed(":nope"), Reply::Error(_)));
+    let Reply::Error(err) = repl.feed("(\\{x: #int} => x)(x = 'one')") else {
+        panic!("expected a type error")
+    };
+    assert!(err.contains("expected #int, found #text"), "{err}");
+    assert_eq!(repl.feed("a = 2"), output("(a = 2)"));
+    assert_eq!(repl.feed("a"), output("2"));
+    assert_eq!(repl.feed(":quit"), Reply::Quit);
+}
+
+#[test]
+fn repl_ast() {
+    let mut repl = Repl::new(Style::Plain);
+    let Reply::Output(ast) = repl.feed(":ast x") else {
+        panic!("expected the syntax tree")
+    };
+    assert!(ast.contains("Get("), "{ast}");
+}
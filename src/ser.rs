@@ -0,0 +1,978 @@
+use std::{fmt, sync::Arc};
+
+use serde::{
+    de::{
+        self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    },
+    ser::{self, Serialize},
+};
+use thiserror::Error;
+
+use crate::{export::Path, AsTyp, Key, PrimType, Primitive, Term, ToTerm, Type};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("at {path}: {msg}")]
+pub struct SerError {
+    pub path: Path,
+    pub msg: String,
+}
+
+impl SerError {
+    fn new(path: &Path, msg: impl Into<String>) -> Self {
+        SerError {
+            path: path.clone(),
+            msg: msg.into(),
+        }
+    }
+
+    /// Places the error raised for the value at `path`, unless it already comes from a nested value
+    fn at(mut self, path: &Path) -> Self {
+        if self.path.0.is_empty() {
+            self.path = path.clone();
+        }
+        self
+    }
+}
+
+impl ser::Error for SerError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerError::new(&Path::default(), msg.to_string())
+    }
+}
+
+/// Raised by the types which reject the values made up by [`type_of`]
+impl de::Error for SerError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        let msg = format!("the type rejects the made up value, {msg}");
+        SerError::new(&Path::default(), msg)
+    }
+}
+
+/// Builds the kers term from the Rust value
+///
+/// Structs and maps become records of `Term::Set`s joined with `Term::Append`,
+/// sequences and tuples become records keyed by indices, numbers and strings become primitives.
+/// `None` and the unit become the empty record `()`, unit enum variants become texts with their names
+/// and the other variants become records with a single field named after the variant.
+/// Booleans and integers which do not fit into `#int` have no kers representation.
+pub fn to_term<T: Serialize + ?Sized>(value: &T) -> Result<Arc<Term>, SerError> {
+    let path = Path::default();
+    value
+        .serialize(TermSerializer { path: &path })
+        .map(Arc::new)
+        .map_err(|err| err.at(&path))
+}
+
+/// Record type of the terms built by [`to_term`] from the values of `T`
+///
+/// The type is traced while deserializing a made up value of `T`, where integers are `1`, floats are `0.0`,
+/// texts are empty and enums take their first variant. Types which reject such values, like `IpAddr`
+/// parsed from a text, have no derived type.
+/// Structs become intersections of `Type::Field`s, tuples become positional record types.
+/// Values whose shape differs between the values of the type, such as options, sequences, maps and enums,
+/// get the type `{}` any value has.
+pub fn type_of<T: DeserializeOwned>() -> Result<Arc<Term>, SerError> {
+    let path = Path::default();
+    let mut typ = any_type();
+    T::deserialize(Tracer {
+        typ: &mut typ,
+        path: &path,
+    })
+    .map_err(|err| err.at(&path))?;
+    Ok(Arc::new(typ))
+}
+
+struct TermSerializer<'p> {
+    path: &'p Path,
+}
+
+impl<'p> TermSerializer<'p> {
+    fn unsupported(&self, what: &str) -> SerError {
+        SerError::new(self.path, format!("{what} has no kers representation"))
+    }
+
+    fn integer(&self, n: impl TryInto<i64> + fmt::Display + Copy) -> Result<Term, SerError> {
+        match n.try_into() {
+            Ok(n) => Ok(n.to_term()),
+            Err(_) => Err(self.unsupported(&format!("the integer {n} out of the #int range"))),
+        }
+    }
+
+    fn fields(&self) -> Fields<'p> {
+        Fields {
+            path: self.path,
+            fields: vec![],
+            key: None,
+        }
+    }
+}
+
+impl<'p> ser::Serializer for TermSerializer<'p> {
+    type Ok = Term;
+    type Error = SerError;
+    type SerializeSeq = Fields<'p>;
+    type SerializeTuple = Fields<'p>;
+    type SerializeTupleStruct = Fields<'p>;
+    type SerializeTupleVariant = Tagged;
+    type SerializeMap = Fields<'p>;
+    type SerializeStruct = Fields<'p>;
+    type SerializeStructVariant = Tagged;
+
+    fn serialize_bool(self, _v: bool) -> Result<Term, SerError> {
+        Err(self.unsupported("a boolean"))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Term, SerError> {
+        self.integer(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Term, SerError> {
+        Ok(f64::from(v).to_term())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Term, SerError> {
+        Ok(v.to_term())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Term, SerError> {
+        Ok(v.to_string().to_term())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Term, SerError> {
+        Ok(v.to_term())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Term, SerError> {
+        let mut fields = self.fields();
+        for byte in v {
+            ser::SerializeSeq::serialize_element(&mut fields, byte)?;
+        }
+        ser::SerializeSeq::end(fields)
+    }
+
+    fn serialize_none(self) -> Result<Term, SerError> {
+        Ok(Term::Empty)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Term, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Term, SerError> {
+        Ok(Term::Empty)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Term, SerError> {
+        Ok(Term::Empty)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Term, SerError> {
+        Ok(variant.to_term())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Term, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Term, SerError> {
+        let path = self.path.with(&Key::Name(variant.to_string()));
+        let value = value
+            .serialize(TermSerializer { path: &path })
+            .map_err(|err| err.at(&path))?;
+        Ok((variant, value).to_term())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Fields<'p>, SerError> {
+        Ok(self.fields())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Fields<'p>, SerError> {
+        Ok(self.fields())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Fields<'p>, SerError> {
+        Ok(self.fields())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Tagged, SerError> {
+        Ok(Tagged::new(self.path, variant))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Fields<'p>, SerError> {
+        Ok(self.fields())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Fields<'p>, SerError> {
+        Ok(self.fields())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Tagged, SerError> {
+        Ok(Tagged::new(self.path, variant))
+    }
+}
+
+/// Record being serialized, one `Term::Set` per field
+struct Fields<'p> {
+    path: &'p Path,
+    fields: Vec<Term>,
+    /// Key of the map entry whose value is not serialized yet
+    key: Option<Key>,
+}
+
+impl<'p> Fields<'p> {
+    fn field<T: Serialize + ?Sized>(&mut self, key: Key, value: &T) -> Result<(), SerError> {
+        let path = self.path.with(&key);
+        let value = value
+            .serialize(TermSerializer { path: &path })
+            .map_err(|err| err.at(&path))?;
+        self.fields.push(Term::Set {
+            name: key,
+            value: value.to_arc_term(),
+        });
+        Ok(())
+    }
+
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.field(Key::Index(self.fields.len()), value)
+    }
+
+    fn end(self) -> Result<Term, SerError> {
+        Ok(self.fields.as_slice().to_term())
+    }
+}
+
+impl ser::SerializeSeq for Fields<'_> {
+    type Ok = Term;
+    type Error = SerError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Term, SerError> {
+        Fields::end(self)
+    }
+}
+
+impl ser::SerializeTuple for Fields<'_> {
+    type Ok = Term;
+    type Error = SerError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Term, SerError> {
+        Fields::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for Fields<'_> {
+    type Ok = Term;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Term, SerError> {
+        Fields::end(self)
+    }
+}
+
+impl ser::SerializeMap for Fields<'_> {
+    type Ok = Term;
+    type Error = SerError;
+
+    /// Keys have to be texts or non-negative integers, which become indices
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerError> {
+        let key = match key.serialize(TermSerializer { path: self.path })? {
+            Term::Prim(Primitive::Text(name)) => Key::Name(name),
+            Term::Prim(Primitive::Long(idx)) if idx >= 0 => Key::Index(idx as usize),
+            other => {
+                let msg = format!("map key {other} is neither a text nor an index");
+                return Err(SerError::new(self.path, msg));
+            }
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        let key = self
+            .key
+            .take()
+            .expect("values are serialized after their keys");
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Term, SerError> {
+        Fields::end(self)
+    }
+}
+
+impl ser::SerializeStruct for Fields<'_> {
+    type Ok = Term;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerError> {
+        self.field(Key::Name(key.to_string()), value)
+    }
+
+    fn end(self) -> Result<Term, SerError> {
+        Fields::end(self)
+    }
+}
+
+/// Contents of an enum variant, wrapped into a record with a single field named after the variant
+struct Tagged {
+    variant: &'static str,
+    /// Path of the variant contents
+    path: Path,
+    fields: Vec<Term>,
+}
+
+impl Tagged {
+    fn new(parent: &Path, variant: &'static str) -> Self {
+        Tagged {
+            variant,
+            path: parent.with(&Key::Name(variant.to_string())),
+            fields: vec![],
+        }
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: Key, value: &T) -> Result<(), SerError> {
+        let mut fields = Fields {
+            path: &self.path,
+            fields: std::mem::take(&mut self.fields),
+            key: None,
+        };
+        let result = fields.field(key, value);
+        self.fields = fields.fields;
+        result
+    }
+
+    fn end(self) -> Result<Term, SerError> {
+        Ok((self.variant, self.fields.as_slice().to_term()).to_term())
+    }
+}
+
+impl ser::SerializeTupleVariant for Tagged {
+    type Ok = Term;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.field(Key::Index(self.fields.len()), value)
+    }
+
+    fn end(self) -> Result<Term, SerError> {
+        Tagged::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for Tagged {
+    type Ok = Term;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerError> {
+        self.field(Key::Name(key.to_string()), value)
+    }
+
+    fn end(self) -> Result<Term, SerError> {
+        Tagged::end(self)
+    }
+}
+
+fn any_type() -> Term {
+    PrimType::Any.to_term()
+}
+
+/// Deserializer which makes up values of a Rust type, noting the kers types of the parts it is asked for
+struct Tracer<'t, 'p> {
+    typ: &'t mut Term,
+    path: &'p Path,
+}
+
+impl<'t, 'p> Tracer<'t, 'p> {
+    fn prim(self, prim: PrimType) {
+        *self.typ = prim.to_term();
+    }
+}
+
+impl<'de, 't, 'p> de::Deserializer<'de> for Tracer<'t, 'p> {
+    type Error = SerError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, SerError> {
+        Err(SerError::new(
+            self.path,
+            "the type depends on the value, it can not be derived",
+        ))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, SerError> {
+        Err(SerError::new(
+            self.path,
+            "a boolean has no kers representation",
+        ))
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_i8(1)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_i16(1)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_i32(1)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_i64(1)
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_i128(1)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_u8(1)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_u16(1)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_u32(1)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_u64(1)
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Long);
+        visitor.visit_u128(1)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Float);
+        visitor.visit_f32(0.0)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Float);
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Text);
+        visitor.visit_char(' ')
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Text);
+        visitor.visit_str("")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.prim(PrimType::Text);
+        visitor.visit_string(String::new())
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        *self.typ = any_type();
+        visitor.visit_bytes(&[])
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        *self.typ = any_type();
+        visitor.visit_byte_buf(vec![])
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        *self.typ = any_type();
+        visitor.visit_none()
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        *self.typ = any_type();
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        *self.typ = any_type();
+        visitor.visit_seq(Traced::new(self.path, vec![]))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerError> {
+        let keys = (0..len).map(Key::Index).collect();
+        let mut elems = Traced::new(self.path, keys);
+        let value = visitor.visit_seq(&mut elems)?;
+        *self.typ = elems.record_type();
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        *self.typ = any_type();
+        visitor.visit_map(Traced::new(self.path, vec![]))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerError> {
+        let keys = fields
+            .iter()
+            .map(|name| Key::Name(name.to_string()))
+            .collect();
+        let mut fields = Traced::new(self.path, keys);
+        let value = visitor.visit_map(&mut fields)?;
+        *self.typ = fields.record_type();
+        Ok(value)
+    }
+
+    /// Values of different variants have different shapes, the first variant is made up
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerError> {
+        *self.typ = any_type();
+        let Some(variant) = variants.first() else {
+            return Err(SerError::new(
+                self.path,
+                "an enum without variants has no values",
+            ));
+        };
+        let path = self.path.with(&Key::Name(variant.to_string()));
+        visitor.visit_enum(TracedVariant {
+            variant,
+            path: &path,
+        })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerError> {
+        visitor.visit_unit()
+    }
+}
+
+/// Fields of a struct or elements of a tuple made up by the tracer, along with their types
+struct Traced<'p> {
+    path: &'p Path,
+    keys: std::vec::IntoIter<Key>,
+    types: Vec<(Key, Term)>,
+    /// Key whose value is not made up yet
+    key: Option<Key>,
+}
+
+impl<'p> Traced<'p> {
+    fn new(path: &'p Path, keys: Vec<Key>) -> Self {
+        Traced {
+            path,
+            keys: keys.into_iter(),
+            types: vec![],
+            key: None,
+        }
+    }
+
+    fn value<'de, T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+        key: Key,
+    ) -> Result<T::Value, SerError> {
+        let path = self.path.with(&key);
+        let mut typ = any_type();
+        let value = seed
+            .deserialize(Tracer {
+                typ: &mut typ,
+                path: &path,
+            })
+            .map_err(|err| err.at(&path))?;
+        self.types.push((key, typ));
+        Ok(value)
+    }
+
+    /// Intersection of the field types
+    fn record_type(&self) -> Term {
+        let fields: Vec<Term> = self
+            .types
+            .iter()
+            .map(|(key, typ)| {
+                let typ = typ.clone().to_arc_term();
+                Type::Field {
+                    name: key.clone(),
+                    typ,
+                }
+                .to_term()
+            })
+            .collect();
+        if fields.is_empty() {
+            return any_type();
+        }
+        AsTyp(fields.as_slice()).to_term()
+    }
+}
+
+impl<'de> SeqAccess<'de> for Traced<'_> {
+    type Error = SerError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SerError> {
+        match self.keys.next() {
+            Some(key) => self.value(seed, key).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for Traced<'_> {
+    type Error = SerError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SerError> {
+        let Some(key) = self.keys.next() else {
+            return Ok(None);
+        };
+        let name = match &key {
+            Key::Name(name) => name.clone(),
+            Key::Index(idx) => idx.to_string(),
+        };
+        self.key = Some(key);
+        seed.deserialize(name.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, SerError> {
+        let key = self
+            .key
+            .take()
+            .expect("values are made up after their keys");
+        self.value(seed, key)
+    }
+}
+
+/// The enum variant made up by the tracer
+struct TracedVariant<'p> {
+    variant: &'static str,
+    path: &'p Path,
+}
+
+impl<'de, 'p> de::EnumAccess<'de> for TracedVariant<'p> {
+    type Error = SerError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), SerError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'p> de::VariantAccess<'de> for TracedVariant<'p> {
+    type Error = SerError;
+
+    fn unit_variant(self) -> Result<(), SerError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, SerError> {
+        seed.deserialize(Tracer {
+            typ: &mut any_type(),
+            path: self.path,
+        })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, SerError> {
+        de::Deserializer::deserialize_tuple(
+            Tracer {
+                typ: &mut any_type(),
+                path: self.path,
+            },
+            len,
+            visitor,
+        )
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerError> {
+        de::Deserializer::deserialize_struct(
+            Tracer {
+                typ: &mut any_type(),
+                path: self.path,
+            },
+            "",
+            fields,
+            visitor,
+        )
+    }
+}
+
+#[cfg(test)]
+use crate::{
+    de::from_value,
+    evaltime::{checking::TypeChecking, evaluate::Evaluation, values::Value},
+    parse::parse_term,
+};
+#[cfg(test)]
+use serde::{Deserialize, Serialize as Derive};
+#[cfg(test)]
+use std::collections::BTreeMap;
+
+#[test]
+fn serialize_config() {
+    #[derive(Derive, Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        server: Server,
+        replicas: (u8, f64),
+        tags: Vec<String>,
+        limit: Option<u32>,
+        mode: Mode,
+    }
+
+    #[derive(Derive, Deserialize, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Derive, Deserialize, Debug, PartialEq, Clone)]
+    enum Mode {
+        Fast,
+        Limited { rate: i64 },
+        Pair(i64, i64),
+    }
+
+    let config = |mode| Config {
+        name: "api".to_string(),
+        server: Server {
+            host: "localhost".to_string(),
+            port: 8080,
+        },
+        replicas: (3, 0.5),
+        tags: vec!["a".to_string(), "b".to_string()],
+        limit: None,
+        mode,
+    };
+    let term = to_term(&config(Mode::Limited { rate: 10 })).unwrap();
+    let expected = parse_term(
+        "(name = 'api', server = (host = 'localhost', port = 8080), replicas = (3, 0.5), tags = ('a', 'b'), limit = (), mode = (Limited = (rate = 10)))",
+    );
+    assert_eq!(term, expected.unwrap());
+    for mode in [Mode::Fast, Mode::Limited { rate: -1 }, Mode::Pair(1, 2)] {
+        let term = to_term(&config(mode.clone())).unwrap();
+        let value = Evaluation::new(()).eval(&term, &Value::empty()).unwrap();
+        let decoded: Config = from_value(&value).unwrap();
+        assert_eq!(decoded, config(mode));
+    }
+    let map = BTreeMap::from([(1, "one"), (0, "zero")]);
+    assert_eq!(
+        to_term(&map).unwrap(),
+        parse_term("('zero', 'one')").unwrap()
+    );
+    assert_eq!(to_term(&()).unwrap(), Arc::new(Term::Empty));
+}
+
+#[test]
+fn serialize_errors() {
+    let err = to_term(&BTreeMap::from([("debug", vec![true])])).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .debug.0: a boolean has no kers representation"
+    );
+    let err = to_term(&(1, u64::MAX)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .1: the integer 18446744073709551615 out of the #int range has no kers representation"
+    );
+    let err = to_term(&BTreeMap::from([(-1, 1)])).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .: map key -1 is neither a text nor an index"
+    );
+}
+
+#[test]
+fn derive_record_type() {
+    #[derive(Derive, Deserialize)]
+    struct Service {
+        name: String,
+        port: std::num::NonZeroU16,
+        replicas: (u8, f64),
+        backend: Backend,
+    }
+
+    #[derive(Derive, Deserialize)]
+    struct Backend(String);
+
+    let typ = type_of::<Service>().unwrap();
+    let expected =
+        parse_term("{name: #text, port: #int, replicas: {#int, #float}, backend: #text}");
+    assert_eq!(typ, expected.unwrap());
+    let typ = Evaluation::new(()).eval(&typ, &Value::empty()).unwrap();
+    let service = Service {
+        name: "api".to_string(),
+        port: 8080.try_into().unwrap(),
+        replicas: (3, 0.5),
+        backend: Backend("db".to_string()),
+    };
+    let term = to_term(&service).unwrap();
+    let checked = TypeChecking::new(()).check_against(&term, &Value::empty(), &typ);
+    assert!(checked.is_ok(), "{checked:?}");
+}
+
+#[test]
+fn derive_record_type_limits() {
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    enum Mode {
+        Fast,
+        Limited { rate: i64 },
+    }
+
+    // the shapes of these values differ, any value has the type `{}`
+    let any = Arc::new(any_type());
+    assert_eq!(type_of::<Option<u32>>().unwrap(), any);
+    assert_eq!(type_of::<Vec<String>>().unwrap(), any);
+    assert_eq!(type_of::<BTreeMap<String, bool>>().unwrap(), any);
+    assert_eq!(type_of::<Mode>().unwrap(), any);
+    assert_eq!(type_of::<()>().unwrap(), any);
+    let err = type_of::<(i64, bool)>().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .1: a boolean has no kers representation"
+    );
+    let err = type_of::<(String, std::net::IpAddr)>().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "at .1: the type rejects the made up value, invalid IP address syntax"
+    );
+}
@@ -0,0 +1,160 @@
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+/// Runs the binary with the `input` on the standard input
+fn kers(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kers"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the usage errors are reported without reading the input
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn code(output: &Output) -> i32 {
+    output.status.code().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+/// Directory with the files, under the temporary directory of the tests
+fn files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (path, contents) in files {
+        fs::write(dir.join(path), contents).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn exit_codes() {
+    let cases: &[(&[&str], &str, i32)] = &[
+        (&["eval"], "(a = 1)", 0),
+        (&["fmt", "--check"], "(a=1)", 1),
+        (&["eval", "--verbose"], "(a = 1)", 2),
+        (&["eval"], "(a = ", 3),
+        (&["eval"], "(a = 1).b", 4),
+        (&["eval"], "#int & #text", 5),
+        (&["eval", "--format", "json"], "\\{} => 1", 6),
+    ];
+    for (args, input, expected) in cases {
+        let output = kers(args, input);
+        assert_eq!(code(&output), *expected, "{args:?} {input}");
+    }
+    let dir = files("exit-codes", &[("a.kers", "(a = 1)")]);
+    let pinned = format!("import 'a.kers' sha256:{}", "0".repeat(64));
+    let main = dir.join("main.kers");
+    fs::write(&main, pinned).unwrap();
+    let output = kers(&["eval", main.to_str().unwrap()], "");
+    assert_eq!(code(&output), 7);
+    assert!(stderr(&output).contains("the imported file has changed"));
+}
+
+#[test]
+fn commands() {
+    let output = kers(&["eval"], "(a = 1, b = 'two')");
+    assert_eq!(stdout(&output), "(a = 1, b = 'two')\n");
+    let output = kers(&["type"], "(a = 1, b = 'two')");
+    assert_eq!(stdout(&output), "{a: #int, b: #text}\n");
+    // the context of a file is the empty record, its type is the empty record type
+    let output = kers(&["type"], "@@");
+    assert_eq!(stdout(&output), "{}\n");
+    let output = kers(&["eval"], "@@");
+    assert_eq!(stdout(&output), "()\n");
+    let output = kers(&["check"], "(a = 1)");
+    assert_eq!(stdout(&output), "<stdin>: ok\n");
+    let output = kers(&["hash"], "(a = 1)");
+    assert!(stdout(&output).starts_with("sha256:"));
+    let output = kers(&["fmt"], "(a=1;)");
+    assert_eq!(stdout(&output), "(a = 1)\n");
+    let output = kers(&["help"], "");
+    assert_eq!(code(&output), 0);
+    assert!(stdout(&output).starts_with("usage: kers"));
+}
+
+#[test]
+fn arguments() {
+    let formats: [&[&str]; 2] = [&["eval", "--format=json"], &["eval", "--format", "json"]];
+    for args in formats {
+        let output = kers(args, "(a = 1)");
+        assert_eq!(code(&output), 0);
+        assert_eq!(stdout(&output), "{\n  \"a\": 1\n}\n");
+    }
+    let errors: &[(&[&str], &str)] = &[
+        (&[], "missing command"),
+        (&["run"], "unknown command run"),
+        (&["eval", "--format"], "missing value of --format"),
+        (&["eval", "--format=xml"], "unknown format xml"),
+        (&["type", "--format=json"], "unknown option --format=json"),
+        (&["fmt", "--quiet"], "unknown option --quiet"),
+    ];
+    for (args, message) in errors {
+        let output = kers(args, "(a = 1)");
+        assert_eq!(code(&output), 2, "{args:?}");
+        let stderr = stderr(&output);
+        assert!(
+            stderr.starts_with(&format!("{message}\nusage: kers")),
+            "{stderr}"
+        );
+    }
+}
+
+#[test]
+fn files_and_stdin() {
+    let dir = files(
+        "files-and-stdin",
+        &[("good.kers", "(a = 1)"), ("bad.kers", "(a = 'x').@f")],
+    );
+    let good = dir.join("good.kers");
+    let bad = dir.join("bad.kers");
+    let missing = dir.join("missing.kers");
+    let output = kers(&["eval", good.to_str().unwrap(), "-"], "2");
+    assert_eq!(code(&output), 0);
+    assert_eq!(stdout(&output), "(a = 1)\n2\n");
+    // every input is reported, the exit code is the highest one
+    let args = ["eval", missing.to_str().unwrap(), bad.to_str().unwrap()];
+    let output = kers(&args, "");
+    assert_eq!(code(&output), 4);
+    let stderr = stderr(&output);
+    assert!(stderr.contains("missing.kers"), "{stderr}");
+    assert!(stderr.contains("unbound name `f`"), "{stderr}");
+}
+
+#[test]
+fn closed_output() {
+    let input = format!("'{}'", "a".repeat(100_000));
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kers"))
+        .arg("parse")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the output is closed before anything is written, as by `kers parse file | head -0`
+    drop(child.stdout.take());
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(code(&output), 0);
+    assert_eq!(stderr(&output), "");
+}